@@ -0,0 +1,309 @@
+use std::{collections::VecDeque, error::Error, fs::{File, create_dir_all, read_link, remove_file}, io::{ErrorKind, Read, copy}, path::{Component, Path, PathBuf}};
+
+use zip::{ZipArchive, read::ZipFile};
+
+use crate::{check_cancelled, progress::{ProgressEvent, emit}, receipt::{FileSource, record}, sandbox::check_links, store::create_unlinked};
+
+const FILE_TYPE_MASK: u32 = 0o170000;
+const SYMBOLIC_LINK: u32 = 0o120000;
+/// How many links resolving a single path may follow, as on Linux.
+const MAX_LINKS: usize = 40;
+
+/// What an extraction wrote, reported back to the script.
+#[derive(Debug, Default)]
+pub(crate) struct Extracted {
+    pub(crate) files: usize,
+    pub(crate) bytes: u64,
+}
+
+/// The path an entry extracts to relative to the output directory, or `None` when its name is
+/// absolute or climbs out of the directory with `..`.
+fn entry_path(file: &ZipFile) -> Option<PathBuf> {
+    let name = file.enclosed_name()?;
+
+    let mut path = PathBuf::new();
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::ParentDir => {
+                path.pop();
+            },
+            _ => (),
+        }
+    }
+
+    Some(path)
+}
+
+fn is_symbolic_link(file: &ZipFile) -> bool {
+    file.unix_mode().is_some_and(|mode| mode & FILE_TYPE_MASK == SYMBOLIC_LINK)
+}
+
+/// Whether a link at `link`, relative to the output directory, pointing at `target` stays inside
+/// the output directory.
+fn link_is_enclosed(link: &Path, target: &Path) -> bool {
+    let mut depth = link.components().count().saturating_sub(1);
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => (),
+            Component::ParentDir => match depth.checked_sub(1) {
+                Some(parent) => depth = parent,
+                None => return false,
+            },
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+
+    true
+}
+
+/// Resolves `path` the way the file system would, following every symbolic link on the way.
+/// Components that do not exist yet are taken as plain directories. `None` when the links loop.
+fn resolve_links(path: &Path) -> Option<PathBuf> {
+    let mut pending: VecDeque<PathBuf> = path.components().map(|component| PathBuf::from(component.as_os_str())).collect();
+    let mut resolved = PathBuf::new();
+    let mut followed = 0;
+    while let Some(next) = pending.pop_front() {
+        match next.components().next() {
+            Some(Component::Normal(part)) => {
+                let candidate = resolved.join(part);
+                match read_link(&candidate) {
+                    Ok(target) => {
+                        followed += 1;
+                        if followed > MAX_LINKS {
+                            return None;
+                        }
+                        for component in target.components().rev() {
+                            pending.push_front(PathBuf::from(component.as_os_str()));
+                        }
+                    },
+                    Err(_) => resolved = candidate,
+                }
+            },
+            Some(Component::ParentDir) => {
+                resolved.pop();
+            },
+            Some(Component::RootDir) | Some(Component::Prefix(_)) => resolved = next,
+            Some(Component::CurDir) | None => (),
+        }
+    }
+
+    Some(resolved)
+}
+
+/// Whether the link at `link`, relative to the output directory `out_path`, resolves to a path
+/// inside it through the links on disk. `out_path` must be canonical.
+fn link_stays_inside(out_path: &Path, link: &Path) -> bool {
+    resolve_links(&out_path.join(link)).is_some_and(|resolved| resolved.starts_with(out_path))
+}
+
+#[cfg(unix)]
+fn create_link(target: &Path, link: &Path) -> Result<(), Box<dyn Error>> {
+    match remove_file(link) {
+        Ok(()) => (),
+        Err(error) if error.kind() == ErrorKind::NotFound => (),
+        Err(error) => return Err(error.into()),
+    }
+    std::os::unix::fs::symlink(target, link)?;
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn create_link(_target: &Path, _link: &Path) -> Result<(), Box<dyn Error>> {
+    Ok(())
+}
+
+/// Gives an extracted file the permissions it had in the archive, so bundled executables such
+/// as Java runtimes stay executable.
+#[cfg(unix)]
+fn apply_permissions(file: &ZipFile, path: &Path) -> Result<(), Box<dyn Error>> {
+    use std::{fs::{Permissions, set_permissions}, os::unix::fs::PermissionsExt};
+
+    if let Some(mode) = file.unix_mode() {
+        set_permissions(path, Permissions::from_mode(mode & 0o777))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn apply_permissions(_file: &ZipFile, _path: &Path) -> Result<(), Box<dyn Error>> {
+    Ok(())
+}
+
+// https://github.com/zip-rs/zip/blob/master/examples/extract.rs
+/// Extracts every entry of `zip` into `out_path`. Entries whose name or link target would land
+/// outside `out_path`, directly or through a link already on disk, fail the extraction. Links
+/// are checked again once every entry was extracted, since a later link can change where an
+/// earlier one leads.
+pub(crate) fn extract_zip(zip: &str, out_path: &str) -> Result<Extracted, Box<dyn Error>> {
+    let mut archive = ZipArchive::new(File::open(zip)?)?;
+    let out_path = Path::new(out_path);
+
+    let mut links = Vec::new();
+    let mut extracted = Extracted::default();
+    let total = archive.len();
+    for i in 0..total {
+        let mut file = archive.by_index(i)?;
+        check_cancelled()?;
+        emit(ProgressEvent::Extracting {
+            archive: zip.to_string(),
+            entry: file.name().to_string(),
+            index: i,
+            total,
+        });
+
+        let relative = entry_path(&file).ok_or_else(|| format!("{}: entry {} would be extracted outside {}", zip, file.name(), out_path.display()))?;
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        let outpath = out_path.join(&relative);
+        check_links(out_path, &outpath).map_err(|reason| format!("{}: entry {} would be extracted outside {}: {}", zip, file.name(), out_path.display(), reason))?;
+
+        if file.is_dir() {
+            create_dir_all(&outpath)?;
+            continue;
+        }
+
+        if let Some(parent) = outpath.parent() {
+            create_dir_all(parent)?;
+        }
+
+        if is_symbolic_link(&file) {
+            let mut target = String::new();
+            file.read_to_string(&mut target)?;
+            let target = PathBuf::from(target);
+            if !link_is_enclosed(&relative, &target) {
+                return Err(format!("{}: link {} points to {}, outside {}", zip, file.name(), target.display(), out_path.display()).into());
+            }
+
+            create_link(&target, &outpath)?;
+            if !link_stays_inside(&out_path.canonicalize()?, &relative) {
+                let _ = remove_file(&outpath);
+                return Err(format!("{}: link {} points to {}, outside {}", zip, file.name(), target.display(), out_path.display()).into());
+            }
+            links.push((relative, file.name().to_string()));
+            extracted.files += 1;
+        } else {
+            let mut outfile = create_unlinked(&outpath)?;
+            extracted.bytes += copy(&mut file, &mut outfile)?;
+            apply_permissions(&file, &outpath)?;
+            record(&outpath, FileSource::Extract {
+                archive: zip.to_string(),
+                entry: file.name().to_string(),
+            })?;
+            extracted.files += 1;
+        }
+    }
+
+    if !links.is_empty() {
+        let canonical_out = out_path.canonicalize()?;
+        for (link, name) in &links {
+            if !link_stays_inside(&canonical_out, link) {
+                let _ = remove_file(out_path.join(link));
+                return Err(format!("{}: link {} leads outside {} through other links", zip, name, out_path.display()).into());
+            }
+        }
+    }
+
+    Ok(extracted)
+}
+
+/// Extracts a single file of a zip archive to `out_path`.
+pub(crate) fn extract_zip_entry(zip: &str, entry: &str, out_path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut archive = ZipArchive::new(File::open(zip)?)?;
+    let mut file = archive.by_name(entry)?;
+
+    if let Some(parent) = out_path.parent() {
+        create_dir_all(parent)?;
+    }
+    let mut outfile = create_unlinked(out_path)?;
+    copy(&mut file, &mut outfile)?;
+    apply_permissions(&file, out_path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io::{Cursor, Write}, process};
+
+    use zip::{ZipWriter, write::FileOptions};
+
+    use super::*;
+
+    fn entry_path_of(name: &str) -> Option<PathBuf> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file(name, FileOptions::default()).unwrap();
+        writer.write_all(b"contents").unwrap();
+        let mut archive = ZipArchive::new(writer.finish().unwrap()).unwrap();
+        let file = archive.by_index(0).unwrap();
+        entry_path(&file)
+    }
+
+    /// A fresh directory for a test, removed again by the test.
+    fn scratch_directory(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("game-archive-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        create_dir_all(&directory).unwrap();
+        directory.canonicalize().unwrap()
+    }
+
+    #[test]
+    fn entry_path_normalizes_names() {
+        assert_eq!(entry_path_of("a/b.txt"), Some(PathBuf::from("a/b.txt")));
+        assert_eq!(entry_path_of("./a/../b.txt"), Some(PathBuf::from("b.txt")));
+        assert_eq!(entry_path_of("a/"), Some(PathBuf::from("a")));
+    }
+
+    #[test]
+    fn entry_path_rejects_escaping_names() {
+        assert_eq!(entry_path_of("../b.txt"), None);
+        assert_eq!(entry_path_of("a/../../b.txt"), None);
+        assert_eq!(entry_path_of("/etc/passwd"), None);
+    }
+
+    #[test]
+    fn link_is_enclosed_counts_depth() {
+        assert!(link_is_enclosed(Path::new("link"), Path::new("target")));
+        assert!(link_is_enclosed(Path::new("a/link"), Path::new("../target")));
+        assert!(link_is_enclosed(Path::new("a/link"), Path::new("./b/../../target")));
+        assert!(link_is_enclosed(Path::new("link"), Path::new(".")));
+    }
+
+    #[test]
+    fn link_is_enclosed_rejects_escaping_targets() {
+        assert!(!link_is_enclosed(Path::new("link"), Path::new("..")));
+        assert!(!link_is_enclosed(Path::new("a/link"), Path::new("../../target")));
+        assert!(!link_is_enclosed(Path::new("link"), Path::new("b/../../target")));
+        assert!(!link_is_enclosed(Path::new("link"), Path::new("/etc/passwd")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn link_stays_inside_follows_links_on_disk() {
+        let directory = scratch_directory("links");
+        let out = directory.join("out");
+        create_dir_all(out.join("sub")).unwrap();
+
+        // `l1 -> .` then `l1/l2 -> ..` passes the check on the target text, yet points at the
+        // parent of the output directory.
+        create_link(Path::new("."), &out.join("l1")).unwrap();
+        assert!(link_stays_inside(&out, Path::new("l1")));
+        assert!(link_is_enclosed(Path::new("l1/l2"), Path::new("..")));
+        create_link(Path::new(".."), &out.join("l1/l2")).unwrap();
+        assert!(!link_stays_inside(&out, Path::new("l2")));
+
+        create_link(Path::new("../sub"), &out.join("sub/inner")).unwrap();
+        assert!(link_stays_inside(&out, Path::new("sub/inner")));
+        create_link(Path::new("missing/file"), &out.join("dangling")).unwrap();
+        assert!(link_stays_inside(&out, Path::new("dangling")));
+
+        create_link(Path::new("loop"), &out.join("loop")).unwrap();
+        assert!(!link_stays_inside(&out, Path::new("loop")));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,48 @@
+use std::{error::Error, fmt, sync::{Arc, atomic::{AtomicBool, Ordering}}};
+
+/// The error returned by an operation that stopped because its token was cancelled.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "Operation cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+/// Whether `error` is the result of a cancellation rather than a failure.
+pub fn is_cancelled(error: &(dyn Error + 'static)) -> bool {
+    error.downcast_ref::<Cancelled>().is_some()
+}
+
+/// Lets another thread, such as a Ctrl-C handler, stop a running download or install. Clones
+/// share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Fails with `Cancelled` once the token was cancelled.
+    pub(crate) fn check(&self) -> Result<(), Box<dyn Error>> {
+        if self.is_cancelled() {
+            Err(Box::new(Cancelled))
+        } else {
+            Ok(())
+        }
+    }
+}
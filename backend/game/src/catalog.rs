@@ -0,0 +1,140 @@
+use std::{error::Error, fs::read_dir, path::{Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+use settings::SettingManager;
+
+use crate::{Manifest, installation_directory, installation_source, installed_ids, network::Network, receipt::Receipt, repositories, runtime::block_on};
+
+/// The file at the root of a repository listing the installations it serves.
+pub const CATALOG_FILE: &str = "catalog.json";
+
+/// An installation present in `installation/files`, as shown by `game list`.
+#[derive(Debug, Clone)]
+pub struct InstalledInstallation {
+    pub id: String,
+    pub name: String,
+    pub version: Option<String>,
+    pub parent: Option<String>,
+    /// Seconds since the unix epoch, unknown for installations downloaded but never installed.
+    pub installed_at: Option<u64>,
+    /// Bytes taken by the installation directory and the files its install produced.
+    pub disk_usage: u64,
+    /// The location of the repository it was downloaded from.
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Catalog {
+    #[serde(default)]
+    pub installations: Vec<CatalogEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub parent: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+impl CatalogEntry {
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.id)
+    }
+
+    /// Whether `term` appears in the id, name or description, ignoring case.
+    pub fn matches(&self, term: &str) -> bool {
+        let term = term.to_lowercase();
+        [Some(&self.id), self.name.as_ref(), self.description.as_ref()].iter()
+            .flatten()
+            .any(|field| field.to_lowercase().contains(&term))
+    }
+}
+
+/// A catalog entry together with the repository serving it.
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub entry: CatalogEntry,
+    pub repository: String,
+    pub installed: bool,
+}
+
+fn directory_size(directory: &Path) -> u64 {
+    let entries = match read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+
+    entries.flatten().map(|entry| match entry.metadata() {
+        Ok(metadata) if metadata.is_dir() => directory_size(&entry.path()),
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    }).sum()
+}
+
+/// Every installation present locally, sorted by id.
+pub fn list_installations() -> Result<Vec<InstalledInstallation>, Box<dyn Error>> {
+    let mut installations = Vec::new();
+    for id in installed_ids()? {
+        let directory = installation_directory(&id);
+        let manifest = Manifest::load(&id).ok();
+        let receipt = Receipt::load(&id)?;
+
+        let mut disk_usage = directory_size(&directory);
+        if let Some(receipt) = &receipt {
+            for file in &receipt.files {
+                let path = PathBuf::from(&file.path);
+                if !path.starts_with(&directory) {
+                    disk_usage += path.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+                }
+            }
+        }
+
+        installations.push(InstalledInstallation {
+            name: manifest.as_ref().map(|manifest| manifest.display_name().to_string()).unwrap_or_else(|| id.clone()),
+            version: manifest.as_ref().and_then(|manifest| manifest.version.clone()),
+            parent: manifest.and_then(|manifest| manifest.parent),
+            installed_at: receipt.map(|receipt| receipt.installed_at),
+            disk_usage,
+            source: installation_source(&id),
+            id,
+        });
+    }
+
+    Ok(installations)
+}
+
+/// Searches the catalogs of every configured repository for `term`. An empty term lists
+/// everything. Repositories without a catalog are skipped, and an installation served by
+/// several repositories is only reported for the first of them.
+pub fn search_installations(term: &str, settings: &SettingManager) -> Result<Vec<SearchResult>, Box<dyn Error>> {
+    let network = Network::new(settings)?;
+    let installed = installed_ids()?;
+
+    let mut results: Vec<SearchResult> = Vec::new();
+    for repository in repositories(settings)? {
+        let catalog = block_on(repository.fetch(&network, CATALOG_FILE))
+            .map_err(|error| format!("Error downloading the catalog of {}: {}", repository.location(), error))?;
+        let catalog: Catalog = match catalog {
+            Some(catalog) => serde_json::from_slice(&catalog).map_err(|error| format!("{}/{}: {}", repository.location(), CATALOG_FILE, error))?,
+            None => continue,
+        };
+
+        for entry in catalog.installations {
+            if entry.matches(term) && !results.iter().any(|result| result.entry.id == entry.id) {
+                results.push(SearchResult {
+                    installed: installed.contains(&entry.id),
+                    repository: repository.location(),
+                    entry,
+                });
+            }
+        }
+    }
+
+    Ok(results)
+}
@@ -0,0 +1,365 @@
+use std::{cell::RefCell, error::Error, fs::{create_dir_all, remove_file, rename}, path::{Path, PathBuf}, sync::{Arc, Condvar, Mutex}, thread};
+
+use reqwest::{StatusCode, header::{CONTENT_RANGE, RANGE}};
+use settings::{Setting, SettingManager};
+use tokio::{fs::{File, OpenOptions}, io::AsyncWriteExt, runtime::Handle, sync::Semaphore};
+
+use crate::{cancel::{CancellationToken, Cancelled, is_cancelled}, hash::Checksum, network::Network, progress::{ProgressEvent, emit}, receipt::{FileSource, ReceiptFile}, runtime::{handle, unblock}, store::{adopt, entry_is_valid, link_into, lock_entry, store_path}};
+
+const DEFAULT_CONCURRENCY: usize = 8;
+
+#[derive(Default)]
+struct Progress {
+    pending: usize,
+    failures: Vec<String>,
+    written: Vec<ReceiptFile>,
+}
+
+#[derive(Default)]
+struct Shared {
+    progress: Mutex<Progress>,
+    idle: Condvar,
+}
+
+/// Runs the downloads requested by scripts as tasks on the download runtime, at most
+/// `download_threads` at a time, sharing a single http client between them. Downloads start
+/// in whatever order the runtime schedules their tasks, not necessarily the order they were
+/// queued. Scripts run on a thread of their own and block on the downloader only when they wait.
+pub(crate) struct Downloader {
+    network: Network,
+    handle: Handle,
+    limit: Arc<Semaphore>,
+    shared: Arc<Shared>,
+}
+
+impl Downloader {
+    pub(crate) fn new(settings: &SettingManager, cancellation: &CancellationToken) -> Result<Downloader, Box<dyn Error>> {
+        let concurrency = match settings.get_setting("download_threads".into()) {
+            Some(Setting::Integer(threads)) if *threads > 0 => *threads as usize,
+            _ => DEFAULT_CONCURRENCY,
+        };
+
+        Ok(Downloader {
+            network: Network::new(settings)?.cancellable(cancellation),
+            handle: handle(),
+            limit: Arc::new(Semaphore::new(concurrency)),
+            shared: Arc::new(Shared::default()),
+        })
+    }
+
+    /// Queues a download to run in the background. Failures are reported by `wait`.
+    pub(crate) fn queue(&self, url: String, path: String, expected: Checksum) {
+        emit(ProgressEvent::FileQueued { url: url.clone(), path: path.clone(), size: expected.size });
+        self.shared.progress.lock().unwrap().pending += 1;
+
+        let network = self.network.clone();
+        let limit = self.limit.clone();
+        let mut pending = PendingDownload {
+            shared: self.shared.clone(),
+            url: url.clone(),
+            outcome: None,
+        };
+        self.handle.spawn(async move {
+            let _permit = limit.acquire_owned().await;
+
+            // Jobs still queued when the downloads are cancelled are dropped without being fetched.
+            let result = if network.cancellation().is_cancelled() {
+                Err(Box::new(Cancelled) as Box<dyn Error>)
+            } else {
+                fetch(&network, &url, &path, &expected).await
+            };
+            pending.finish(result);
+        });
+    }
+
+    /// Downloads while blocking the calling thread, returning once the file is written.
+    pub(crate) fn download_now(&self, url: &str, path: &str, expected: &Checksum) -> Result<(), Box<dyn Error>> {
+        emit(ProgressEvent::FileQueued { url: url.to_string(), path: path.to_string(), size: expected.size });
+        let file = self.handle.block_on(fetch(&self.network, url, path, expected)).inspect_err(|error| {
+            if !is_cancelled(error.as_ref()) {
+                emit(ProgressEvent::Error { message: format!("{}: {}", url, error) });
+            }
+        })?;
+        self.shared.progress.lock().unwrap().written.push(file);
+
+        Ok(())
+    }
+
+    /// The files downloaded since the last call.
+    pub(crate) fn take_written(&self) -> Vec<ReceiptFile> {
+        self.shared.progress.lock().unwrap().written.drain(..).collect()
+    }
+
+    pub(crate) fn cancellation(&self) -> &CancellationToken {
+        self.network.cancellation()
+    }
+
+    /// Blocks until every queued download finished and fails with the list of downloads that
+    /// did not complete since the last call, or with `Cancelled` once the downloads were
+    /// cancelled.
+    pub(crate) fn wait(&self) -> Result<(), Box<dyn Error>> {
+        let mut progress = self.shared.progress.lock().unwrap();
+        while progress.pending > 0 {
+            progress = self.shared.idle.wait(progress).unwrap();
+        }
+        if self.cancellation().is_cancelled() {
+            progress.failures.clear();
+            return Err(Box::new(Cancelled));
+        }
+
+        let failures: Vec<String> = progress.failures.drain(..).collect();
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("{} download(s) failed:\n{}", failures.len(), failures.join("\n")).into())
+        }
+    }
+}
+
+impl Drop for Downloader {
+    /// Waits for downloads still running so none outlive the install that queued them.
+    fn drop(&mut self) {
+        let mut progress = self.shared.progress.lock().unwrap();
+        while progress.pending > 0 {
+            progress = self.shared.idle.wait(progress).unwrap();
+        }
+    }
+}
+
+/// A queued download until its task ends. Dropping it counts the download as finished even when
+/// the task panicked, so `wait` and the downloader's `drop` never wait for a task that is gone.
+struct PendingDownload {
+    shared: Arc<Shared>,
+    url: String,
+    /// The file written, or the error message of a failed download. Cancelled downloads have no
+    /// message.
+    outcome: Option<Result<ReceiptFile, Option<String>>>,
+}
+
+impl PendingDownload {
+    fn finish(&mut self, result: Result<ReceiptFile, Box<dyn Error>>) {
+        self.outcome = Some(result.map_err(|error| {
+            if is_cancelled(error.as_ref()) { None } else { Some(format!("{}: {}", self.url, error)) }
+        }));
+    }
+}
+
+impl Drop for PendingDownload {
+    fn drop(&mut self) {
+        let outcome = self.outcome.take().unwrap_or_else(|| Err(Some(format!("{}: the download stopped unexpectedly", self.url))));
+        let error = {
+            let mut progress = self.shared.progress.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let error = match outcome {
+                Ok(file) => {
+                    progress.written.push(file);
+                    None
+                },
+                Err(None) => None,
+                Err(Some(message)) => {
+                    progress.failures.push(message.clone());
+                    Some(message)
+                },
+            };
+            progress.pending -= 1;
+            if progress.pending == 0 {
+                self.shared.idle.notify_all();
+            }
+            error
+        };
+
+        // Subscribers are not called while the task unwinds, since a second panic would abort.
+        if let Some(message) = error {
+            if !thread::panicking() {
+                emit(ProgressEvent::Error { message });
+            }
+        }
+    }
+}
+
+/// Downloads `url` to `path`. Files with an expected hash go through the shared store, so a
+/// file any installation already downloaded is linked into place instead of fetched again.
+pub(crate) async fn fetch(network: &Network, url: &str, path: &str, expected: &Checksum) -> Result<ReceiptFile, Box<dyn Error>> {
+    let file = fetch_file(network, url, path, expected).await?;
+    emit(ProgressEvent::FileFinished { url: url.to_string(), path: path.to_string(), size: file.size });
+
+    Ok(file)
+}
+
+async fn fetch_file(network: &Network, url: &str, path: &str, expected: &Checksum) -> Result<ReceiptFile, Box<dyn Error>> {
+    let source = FileSource::Download { url: url.to_string() };
+    let entry = match store_path(expected) {
+        Some(entry) => entry,
+        None => {
+            fetch_to_file(network, url, path, Path::new(path), expected).await?;
+            let path = path.to_string();
+            return unblock(move || ReceiptFile::describe(&path, source, None, None)).await;
+        },
+    };
+
+    let lock = lock_entry(&entry);
+    let _guard = lock.lock().await;
+    let adopted = {
+        let (entry, path, expected) = (entry.clone(), PathBuf::from(path), expected.clone());
+        unblock(move || {
+            if entry_is_valid(&entry, &expected)? {
+                return Ok(true);
+            }
+            if expected.matches_file(&path)? {
+                adopt(&path, &entry)?;
+                return Ok(true);
+            }
+            Ok(false)
+        }).await?
+    };
+    if !adopted {
+        fetch_to_file(network, url, path, &entry, expected).await?;
+    }
+    link_into(&entry, Path::new(path))?;
+
+    let (path, store, sha1) = (path.to_string(), entry.to_string_lossy().into_owned(), expected.sha1.clone());
+    unblock(move || ReceiptFile::describe(&path, source, Some(store), sha1.as_deref())).await
+}
+
+/// Downloads `url` to `path`, unless the file there already matches `expected`. When a size or
+/// hash is expected the downloaded file is checked against it. Progress is reported for
+/// `destination`, the path the script asked for, which differs from `path` for store entries.
+///
+/// Data is written to `<path>.part` and only renamed to `path` once complete, so an
+/// interrupted download is resumed with a range request by the next attempt. Partial files are
+/// only kept between runs when a hash is expected, since that is what catches a partial file
+/// that belonged to different contents. A resumed download that fails the check is downloaded
+/// once more from the start.
+async fn fetch_to_file(network: &Network, url: &str, destination: &str, path: &Path, expected: &Checksum) -> Result<(), Box<dyn Error>> {
+    let matches = {
+        let (path, expected) = (path.to_path_buf(), expected.clone());
+        unblock(move || expected.matches_file(&path)).await?
+    };
+    if matches {
+        return Ok(());
+    }
+
+    if let Some(parent_path) = path.parent() {
+        create_dir_all(parent_path)?;
+    }
+
+    let part = part_path(path);
+    if !expected.has_hash() && part.exists() {
+        remove_file(&part)?;
+    }
+
+    let mut resumed = part.metadata().is_ok_and(|metadata| metadata.len() > 0);
+    loop {
+        if let Err(error) = network.retry(|| fetch_part(network, url, destination, &part)).await {
+            // A cancelled download is not resumed, so its partial file is only in the way.
+            if is_cancelled(error.as_ref()) {
+                let _ = remove_file(&part);
+            }
+            return Err(error);
+        }
+
+        let verified = {
+            let (part, expected) = (part.clone(), expected.clone());
+            unblock(move || expected.verify_file(&part)).await
+        };
+        match verified {
+            Ok(()) => break,
+            Err(error) => {
+                let _ = remove_file(&part);
+                // The partial file may have held the start of different contents.
+                if !resumed {
+                    return Err(error);
+                }
+                resumed = false;
+            },
+        }
+    }
+    rename(&part, path)?;
+
+    Ok(())
+}
+
+fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// Fetches the rest of `url` into `part`, continuing from its current length when the server
+/// honours range requests and starting over when it does not.
+async fn fetch_part(network: &Network, url: &str, destination: &str, part: &Path) -> Result<(), Box<dyn Error>> {
+    let (mut response, offset) = loop {
+        let offset = match part.metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        };
+
+        let mut request = network.client().get(url);
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={}-", offset));
+        }
+        let response = network.read(request.send()).await?;
+
+        if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            remove_file(part)?;
+            continue;
+        }
+        break (response.error_for_status()?, offset);
+    };
+
+    let resumed = offset > 0
+        && response.status() == StatusCode::PARTIAL_CONTENT
+        && response.headers().get(CONTENT_RANGE)
+            .and_then(|range| range.to_str().ok())
+            .is_some_and(|range| range.starts_with(&format!("bytes {}-", offset)));
+
+    let (mut file, mut downloaded) = if resumed {
+        (OpenOptions::new().append(true).open(part).await?, offset)
+    } else {
+        (File::create(part).await?, 0)
+    };
+    let total = response.content_length().map(|length| length + downloaded);
+
+    loop {
+        let chunk = match network.read(response.chunk()).await? {
+            Some(chunk) => chunk,
+            None => break,
+        };
+        network.cancellation().check()?;
+        file.write_all(&chunk).await?;
+
+        downloaded += chunk.len() as u64;
+        emit(ProgressEvent::BytesDownloaded { url: url.to_string(), path: destination.to_string(), bytes: chunk.len() as u64, downloaded, total });
+    }
+    file.flush().await?;
+
+    Ok(())
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<Downloader>> = const { RefCell::new(None) };
+}
+
+/// Makes a downloader available to the script builtins running on this thread until the
+/// scope is dropped, which also waits for its worker threads to exit.
+pub(crate) struct DownloadScope;
+
+impl DownloadScope {
+    pub(crate) fn enter(downloader: Downloader) -> DownloadScope {
+        ACTIVE.with(|active| *active.borrow_mut() = Some(downloader));
+        DownloadScope
+    }
+}
+
+impl Drop for DownloadScope {
+    fn drop(&mut self) {
+        let downloader = ACTIVE.with(|active| active.borrow_mut().take());
+        drop(downloader);
+    }
+}
+
+pub(crate) fn with_downloader<R>(f: impl FnOnce(&Downloader) -> R) -> Result<R, Box<dyn Error>> {
+    ACTIVE.with(|active| match active.borrow().as_ref() {
+        Some(downloader) => Ok(f(downloader)),
+        None => Err("Downloads are not available outside of an install or launch".into()),
+    })
+}
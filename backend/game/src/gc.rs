@@ -0,0 +1,178 @@
+use std::{collections::{HashMap, HashSet}, error::Error, fs::{self, read_dir}, io::ErrorKind, path::{Path, PathBuf}};
+
+use crate::{installation_directory, installed_ids, permission::DEFAULT_WRITE_ROOTS, receipt::{receipt_ids, Receipt}, store::STORE_DIRECTORY};
+
+#[derive(Debug, Default)]
+pub struct GarbageReport {
+    /// Files that are no longer referenced by any installed installation.
+    pub files: Vec<PathBuf>,
+    /// Bytes freed by removing them. Hard linked files only count once their last link goes.
+    pub reclaimable: u64,
+    /// Receipts of installations whose files directory no longer exists.
+    pub orphaned_receipts: Vec<String>,
+    /// Installations without a receipt, such as ones installed by an older launcher. Nothing
+    /// tells which shared files they use, so the shared directories are left alone while there
+    /// are any.
+    pub unrecorded: Vec<String>,
+}
+
+/// Every regular file below `directory`. Symbolic links are neither followed nor listed.
+fn regular_files(directory: &Path, files: &mut Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let read = match read_dir(directory) {
+        Ok(read) => read,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error.into()),
+    };
+
+    for entry in read {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            regular_files(&entry.path(), files)?;
+        } else if file_type.is_file() {
+            files.push(entry.path());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn link_identity(metadata: &fs::Metadata) -> Option<((u64, u64), u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some(((metadata.dev(), metadata.ino()), metadata.nlink()))
+}
+
+#[cfg(not(unix))]
+fn link_identity(_: &fs::Metadata) -> Option<((u64, u64), u64)> {
+    None
+}
+
+/// Counts the bytes removing `files` frees. A hard linked file only frees space when every one
+/// of its links is among the removed files.
+pub(crate) fn reclaimable_space(files: &[PathBuf]) -> u64 {
+    let mut reclaimable = 0;
+    let mut links: HashMap<(u64, u64), (u64, u64, u64)> = HashMap::new();
+    for file in files {
+        let metadata = match fs::symlink_metadata(file) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        match link_identity(&metadata) {
+            Some((identity, count)) => {
+                let entry = links.entry(identity).or_insert((0, count, metadata.len()));
+                entry.0 += 1;
+            },
+            None => reclaimable += metadata.len(),
+        }
+    }
+
+    for (removed, count, size) in links.values() {
+        if removed >= count {
+            reclaimable += size;
+        }
+    }
+
+    reclaimable
+}
+
+/// The files and store entries the receipts of installed installations refer to.
+pub(crate) struct References {
+    pub(crate) paths: HashSet<PathBuf>,
+    pub(crate) entries: HashSet<PathBuf>,
+    /// Receipts of installations whose files directory no longer exists.
+    pub(crate) orphaned: Vec<(String, Receipt)>,
+}
+
+/// Gathers what installed installations reference, leaving out the receipt of `excluding`.
+pub(crate) fn collect_references(excluding: Option<&str>) -> Result<References, Box<dyn Error>> {
+    let mut references = References {
+        paths: HashSet::new(),
+        entries: HashSet::new(),
+        orphaned: Vec::new(),
+    };
+
+    for id in receipt_ids()? {
+        if Some(id.as_str()) == excluding {
+            continue;
+        }
+        let receipt = match Receipt::load(&id)? {
+            Some(receipt) => receipt,
+            None => continue,
+        };
+        if !installation_directory(&id).is_dir() {
+            references.orphaned.push((id, receipt));
+            continue;
+        }
+
+        for file in receipt.all_files() {
+            references.paths.insert(PathBuf::from(&file.path));
+            if let Some(store) = &file.store {
+                references.entries.insert(PathBuf::from(store));
+            }
+        }
+    }
+
+    Ok(references)
+}
+
+/// Finds store entries and installed files that no installed installation references anymore,
+/// removing them unless `dry_run` is set.
+///
+/// Installations count as installed while both their receipt and their files directory exist.
+/// Files recorded only by the receipts of installations whose files were deleted are garbage,
+/// as are files in the shared libraries, assets and natives directories no installed receipt
+/// lists, such as libraries a newer version of an installation stopped downloading, and every
+/// store entry no installed receipt links to. The shared directories are skipped while any
+/// installation has no receipt, and partial downloads in them are kept to be resumed.
+pub fn collect_garbage(dry_run: bool) -> Result<GarbageReport, Box<dyn Error>> {
+    let references = collect_references(None)?;
+
+    let mut report = GarbageReport::default();
+    let mut found = HashSet::new();
+    for (id, receipt) in &references.orphaned {
+        for file in receipt.all_files() {
+            let path = PathBuf::from(&file.path);
+            if !references.paths.contains(&path) && path.is_file() && found.insert(path.clone()) {
+                report.files.push(path);
+            }
+        }
+        report.orphaned_receipts.push(id.clone());
+    }
+
+    let recorded = receipt_ids()?;
+    report.unrecorded = installed_ids()?.into_iter().filter(|id| !recorded.contains(id)).collect();
+    if report.unrecorded.is_empty() {
+        let mut shared = Vec::new();
+        for root in DEFAULT_WRITE_ROOTS {
+            regular_files(Path::new(root), &mut shared)?;
+        }
+        for file in shared {
+            let partial = file.extension().is_some_and(|extension| extension == "part");
+            if !partial && !references.paths.contains(&file) && found.insert(file.clone()) {
+                report.files.push(file);
+            }
+        }
+    }
+
+    let mut entries = Vec::new();
+    regular_files(Path::new(STORE_DIRECTORY), &mut entries)?;
+    for entry in entries {
+        if !references.entries.contains(&entry) {
+            report.files.push(entry);
+        }
+    }
+
+    report.reclaimable = reclaimable_space(&report.files);
+
+    if !dry_run {
+        for file in &report.files {
+            fs::remove_file(file)?;
+        }
+        for id in &report.orphaned_receipts {
+            Receipt::remove(id)?;
+        }
+    }
+
+    Ok(report)
+}
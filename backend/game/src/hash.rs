@@ -0,0 +1,118 @@
+use std::{error::Error, fs::File, io::{self, ErrorKind, Read}, path::Path};
+
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+pub(crate) fn sha1_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha1::digest(bytes))
+}
+
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+fn digest_file<D: Digest>(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut digest = D::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        digest.update(&buffer[..read]);
+    }
+
+    Ok(hex::encode(digest.finalize()))
+}
+
+pub(crate) fn sha1_file(path: &Path) -> io::Result<String> {
+    digest_file::<Sha1>(path)
+}
+
+pub(crate) fn sha256_file(path: &Path) -> io::Result<String> {
+    digest_file::<Sha256>(path)
+}
+
+/// The size and hashes a file is expected to have. Any of them may be unknown.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Checksum {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha1: Option<String>,
+}
+
+impl Checksum {
+    pub fn has_hash(&self) -> bool {
+        self.sha256.is_some() || self.sha1.is_some()
+    }
+
+    /// Checks contents already in memory, naming `name` in the error.
+    pub fn verify(&self, name: &str, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        if let Some(size) = self.size {
+            if bytes.len() as u64 != size {
+                return Err(format!("{}: expected {} bytes, got {}", name, size, bytes.len()).into());
+            }
+        }
+        if let Some(expected) = &self.sha256 {
+            let actual = sha256_hex(bytes);
+            if !actual.eq_ignore_ascii_case(expected) {
+                return Err(format!("{}: expected sha256 {}, got {}", name, expected, actual).into());
+            }
+        }
+        if let Some(expected) = &self.sha1 {
+            let actual = sha1_hex(bytes);
+            if !actual.eq_ignore_ascii_case(expected) {
+                return Err(format!("{}: expected sha1 {}, got {}", name, expected, actual).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks a file on disk without reading it into memory.
+    pub fn verify_file(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let name = path.display();
+        if let Some(size) = self.size {
+            let actual = path.metadata()?.len();
+            if actual != size {
+                return Err(format!("{}: expected {} bytes, got {}", name, size, actual).into());
+            }
+        }
+        if let Some(expected) = &self.sha256 {
+            let actual = sha256_file(path)?;
+            if !actual.eq_ignore_ascii_case(expected) {
+                return Err(format!("{}: expected sha256 {}, got {}", name, expected, actual).into());
+            }
+        }
+        if let Some(expected) = &self.sha1 {
+            let actual = sha1_file(path)?;
+            if !actual.eq_ignore_ascii_case(expected) {
+                return Err(format!("{}: expected sha1 {}, got {}", name, expected, actual).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether `path` already holds the expected file. Only files with a known hash can match,
+    /// a size alone is not enough to skip a download.
+    pub fn matches_file(&self, path: &Path) -> Result<bool, Box<dyn Error>> {
+        if !self.has_hash() {
+            return Ok(false);
+        }
+
+        match path.metadata() {
+            Ok(metadata) if metadata.is_file() => (),
+            Ok(_) => return Ok(false),
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(error) => return Err(error.into()),
+        }
+
+        Ok(self.verify_file(path).is_ok())
+    }
+}
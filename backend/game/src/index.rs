@@ -0,0 +1,71 @@
+use std::{error::Error, path::{Component, Path}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::hash::Checksum;
+
+pub const INDEX_VERSION: u32 = 2;
+
+/// The `files` index published alongside every installation in a repository.
+///
+/// Version 1 indexes are the original newline separated list of file names and carry no
+/// integrity information. Version 2 indexes are json and list the size and hash of each file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileIndex {
+    pub version: u32,
+    pub files: Vec<IndexEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub path: String,
+    #[serde(flatten)]
+    pub checksum: Checksum,
+}
+
+impl FileIndex {
+    pub fn parse(bytes: &[u8]) -> Result<FileIndex, Box<dyn Error>> {
+        let index = if bytes.trim_ascii_start().starts_with(b"{") {
+            let index: FileIndex = serde_json::from_slice(bytes).map_err(|error| format!("Invalid file index: {}", error))?;
+            if index.version > INDEX_VERSION {
+                return Err(format!("File index version {} is newer than the supported version {}", index.version, INDEX_VERSION).into());
+            }
+            for entry in &index.files {
+                if entry.checksum.size.is_none() || !entry.checksum.has_hash() {
+                    return Err(format!("File index entry {} needs a size and a sha256 or sha1 hash", entry.path).into());
+                }
+            }
+
+            index
+        } else {
+            let files = String::from_utf8(bytes.to_vec())?;
+            FileIndex {
+                version: 1,
+                files: files.lines().filter(|file| !file.is_empty()).map(|file| IndexEntry {
+                    path: file.to_string(),
+                    checksum: Checksum::default(),
+                }).collect(),
+            }
+        };
+
+        for entry in &index.files {
+            let path = Path::new(&entry.path);
+            if !path.components().all(|component| matches!(component, Component::Normal(_))) {
+                return Err(format!("File index entry {} is not a plain relative path", entry.path).into());
+            }
+        }
+
+        Ok(index)
+    }
+
+    pub fn is_verified(&self) -> bool {
+        self.version >= 2
+    }
+}
+
+impl IndexEntry {
+    /// Checks downloaded contents against the size and hashes listed in the index.
+    pub fn verify(&self, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.checksum.verify(&self.path, bytes)
+    }
+}
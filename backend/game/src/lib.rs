@@ -1,11 +1,66 @@
-use std::{any::Any, collections::HashMap, error::Error, fs::{File, create_dir_all, read_to_string, read_dir}, io::{BufReader, Read, Write, copy}, path::{Path, PathBuf}, process::Command, env::current_dir, thread::{self, sleep}, sync::atomic::{AtomicUsize, Ordering}, time::{Duration, SystemTime}};
+use std::{any::Any, collections::HashMap, error::Error, fs::{File, create_dir_all, read_dir}, io::{ErrorKind, Read, Write}, path::{Path, PathBuf}, process::Command, env::current_dir, time::Instant};
 
 use boa::{Context, JsResult, JsString, JsValue, object::{JsObject, Object}, property::Attribute};
-use fancy_regex::Regex;
-use reqwest::blocking::Client;
 use serde_json::Value;
 use settings::{SettingManager, Setting};
-use zip::ZipArchive;
+
+mod manifest;
+
+pub use manifest::{check_id, is_valid_id, Manifest, ManifestProblem, Permissions, SettingDeclaration, validate_installation, LAUNCHER_API_VERSION, MAX_PARENT_DEPTH};
+use manifest::{check_chain, display_chain, resolve_chain};
+
+mod repository;
+
+pub use repository::{installation_source, repositories, Repository};
+use repository::{locate_installation, record_installation_source};
+
+mod hash;
+mod index;
+
+pub use hash::Checksum;
+pub use index::{FileIndex, IndexEntry, INDEX_VERSION};
+
+mod staging;
+mod downloader;
+mod network;
+mod store;
+mod receipt;
+mod gc;
+mod verify;
+mod uninstall;
+mod catalog;
+mod update;
+mod progress;
+mod cancel;
+mod runtime;
+mod sandbox;
+mod archive;
+mod permission;
+mod trust;
+mod script;
+
+use staging::Staging;
+use downloader::{with_downloader, DownloadScope, Downloader};
+use network::Network;
+use script::{new_context, run_script};
+
+pub use receipt::{FileSource, Receipt, ReceiptFile};
+use receipt::{take_recorded, RecordingScope};
+pub use gc::{collect_garbage, GarbageReport};
+pub use catalog::{list_installations, search_installations, Catalog, CatalogEntry, InstalledInstallation, SearchResult, CATALOG_FILE};
+pub use cancel::{is_cancelled, CancellationToken, Cancelled};
+use runtime::{block_on, unblock};
+pub use sandbox::SHARED_ROOTS;
+use sandbox::SandboxScope;
+pub use permission::{grant_permissions, granted_permissions, permissions_required, ungranted_permissions, PermissionRequest, PermissionsRequired, DEFAULT_HOSTS, DEFAULT_WRITE_ROOTS};
+use permission::{check_granted, missing_permissions};
+pub use trust::{IndexSignature, TrustStore, SIGNATURE_FILE};
+use trust::{record_signature, remove_signature, verify_installed};
+pub use progress::{subscribe, Phase, ProgressEvent, Subscription};
+use progress::emit;
+pub use update::{check_updates, update_installations, InstallationUpdate};
+pub use uninstall::{uninstall_installation, UninstallReport};
+pub use verify::{repair_installation, verify_installation, BrokenFile, FileProblem, RepairReport};
 
 const OS: &'static str = if cfg!(windows) {
             "windows"
@@ -19,10 +74,10 @@ trait IntoResult<T, U> {
     fn into_result(self) -> Result<T, U>;
 }
 
-impl IntoResult<(), Box<dyn Error>> for JsResult<()> {
-    fn into_result(self) -> Result<(), Box<dyn Error>> {
+impl<T> IntoResult<T, Box<dyn Error>> for JsResult<T> {
+    fn into_result(self) -> Result<T, Box<dyn Error>> {
         match self {
-            Ok(_) => Ok(()),
+            Ok(value) => Ok(value),
             Err(error) => Err(error.display().to_string().into())
         }
     }
@@ -37,320 +92,260 @@ impl<T: Any, U: Error> IntoResult<JsValue, JsValue> for Result<T, U> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Installation {
     parent: Option<Box<Installation>>,
     id: String,
-    scripts: HashMap<String, String>,
+    manifest: Manifest,
+    chain: Vec<String>,
 }
 
 impl Installation {
     fn get_script(&self, id: String) -> Option<&String> {
-        self.scripts.get(&id)
+        self.manifest.scripts.get(&id)
     }
-}
-
-fn apply_special_params(arguments: &Vec<String>, special_params: &HashMap<&str, String>) -> Vec<String> {
-    arguments.iter().map(|argument| {
-        let mut new_argument = argument.clone();
-        for (special_param, special_value) in special_params {
-            new_argument = new_argument.replace(format!("{{{}}}", special_param).as_str(), special_value);
-        }
-
-        new_argument
-    }).collect()
-}
-
-pub fn download_installation(id: String) -> Result<(), Box<dyn  Error>> {
-    let base_url = format!("https://raw.githubusercontent.com/proton-launcher/asset/main/installation/{}", id);
-    let files_url = format!("{}/files", base_url);
 
-    let parent_folder = format!("installation/files/{}", id);
-    let _ = create_dir_all(&parent_folder);
-    
-    let client = Client::new();
-    
-    let files = client.get(files_url).send()?.text()?;
-    let files: Vec<&str> = files.split("\n").collect();
-    for file in files {
-        if file.is_empty() { continue; }
-        let url = format!("{}/{}", base_url, file);
-        let text = client.get(url).send()?.text()?;
-
-        let mut file = File::create(format!("{}/{}", parent_folder, file))?;
-        file.write_all(text.as_bytes())?;
+    pub fn id(&self) -> &str {
+        &self.id
     }
-    
-    let mut info_json = String::new();
 
-    let mut file = File::open(format!("{}/info.json", parent_folder))?;
-    file.read_to_string(&mut info_json)?;
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
 
-    let info_json: Value = serde_json::from_str(&info_json)?;
-    match info_json["parent"].as_str() {
-        Some(parent) => {
-            download_installation(parent.to_string())?;
-        },
-        None => (),
-    };
+    /// Ids of this installation and every ancestor, starting with this installation.
+    pub fn chain(&self) -> &[String] {
+        &self.chain
+    }
 
-    Ok(())
+    pub fn chain_display(&self) -> String {
+        display_chain(&self.chain)
+    }
 }
 
-pub fn parse_installation(id: String) -> Result<Installation, Box<dyn Error>> {
-    let files_directory = format!("installation/files/{}", id);
-    let info_file = format!("{}/info.json", files_directory);
-    let info_file = File::open(info_file)?;
-    let info_file_json: Value = serde_json::from_reader(BufReader::new(info_file))?;
-
-    let parent = match info_file_json["parent"].as_str() {
-        Some(parent) => Some(Box::new(parse_installation(parent.to_string())?)),
-        None => None
-    };
-
-    let id = info_file_json["id"].as_str().ok_or("ID not found")?.to_string();
-
-    let scripts = match info_file_json["scripts"].as_object() {
-        Some(scripts) => {
-            let mut map = HashMap::new();
-
-            for (id, location) in scripts {
-                map.insert(id.clone(), location.as_str().ok_or("script not string")?.to_string());
-            }
-
-            map
-        },
-        None => HashMap::new()
-    };
-
-    Ok(Installation {
-        parent,
-        id,
-        scripts,
-    })
+pub(crate) fn installation_directory(id: &str) -> PathBuf {
+    Path::new("installation/files").join(id)
 }
 
-static THREAD_COUNT: AtomicUsize = AtomicUsize::new(0);
-
-fn download(_: &JsValue, args: &[JsValue], _: &mut Context) -> Result<JsValue, JsValue> {
-    let url = args[0].as_string().ok_or("Invalid argument for download")?.to_string().clone();
-    let path = args[1].as_string().ok_or("Invalid argument for download")?.to_string().clone();
-    let single_thread = if args.len() > 2 {
-        args[2].as_boolean().ok_or("Invalid argument for download")?
-    } else {
-        false
+/// Ids of every installation present in `installation/files`, sorted.
+pub fn installed_ids() -> Result<Vec<String>, Box<dyn Error>> {
+    let mut ids = Vec::new();
+    let entries = match read_dir("installation/files") {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(ids),
+        Err(error) => return Err(error.into()),
     };
 
-    if single_thread {
-        let response = match reqwest::blocking::get(url) {
-            Ok(response) => response,
-            Err(error) => return Err(JsValue::String(JsString::from(error.to_string())))
-        };
-        let bytes = response.bytes().unwrap();
-    
-        let parent_path = Path::new(&path).parent().unwrap();
-        create_dir_all(parent_path).unwrap();
-    
-        let mut file = File::create(path).unwrap();
-        file.write_all(&bytes).unwrap();
-    } else {
-        thread::spawn(|| {
-            while THREAD_COUNT.load(Ordering::SeqCst) > 25 {
-                sleep(Duration::from_millis(250));
+    for entry in entries {
+        let entry = entry?;
+        if entry.path().is_dir() {
+            if let Some(id) = entry.file_name().to_str() {
+                ids.push(id.to_string());
             }
-
-            THREAD_COUNT.fetch_add(1, Ordering::SeqCst);
-
-            let response = match reqwest::blocking::get(url) {
-                Ok(response) => response,
-                Err(_) => return
-            };
-            let bytes = response.bytes().unwrap();
-        
-            let parent_path = Path::new(&path).parent().unwrap();
-            create_dir_all(parent_path).unwrap();
-        
-            let mut file = File::create(path).unwrap();
-            file.write_all(&bytes).unwrap();
-
-            THREAD_COUNT.fetch_sub(1, Ordering::SeqCst);
-        });
+        }
     }
-    
-    Ok(JsValue::Null)
-}
+    ids.sort();
 
-fn extract(_: &JsValue, args: &[JsValue], _: &mut Context) -> Result<JsValue, JsValue> {
-    let zip = &args[0].as_string().unwrap().to_string();
-    let location = &args[1].as_string().unwrap().to_string();
-    match extract_zip(zip.as_str(), location.as_str()) {
-        Ok(_) => Ok(JsValue::Null),
-        Err(e) => Err(format!("Error extracting file {} to {}: {:?}", zip, location, e).into()),
-    }
+    Ok(ids)
 }
 
-// https://github.com/zip-rs/zip/blob/master/examples/extract.rs
-fn extract_zip<'a>(zip: &'a str, out_path: &'a str) -> Result<(), Box<dyn Error>> {
-    let fname = std::path::Path::new(zip);
-    let file = File::open(&fname)?;
-
-    let mut archive = ZipArchive::new(file)?;
-
-    for i in 0..archive.len() {
-        let mut outpath = PathBuf::new();
-        outpath.push(out_path);
-        outpath.push(archive.by_index(i)?.name());
-        let mut file = archive.by_index(i)?;
-
-        if (&*file.name()).ends_with('/') {
-            create_dir_all(&outpath)?;
-        } else {
-            if let Some(p) = outpath.parent() {
-                if !p.exists() {
-                    create_dir_all(&p)?;
-                }
-            }
-            let mut outfile = File::create(&outpath)?;
-            copy(&mut file, &mut outfile)?;
+fn apply_special_params(arguments: &Vec<String>, special_params: &HashMap<&str, String>) -> Vec<String> {
+    arguments.iter().map(|argument| {
+        let mut new_argument = argument.clone();
+        for (special_param, special_value) in special_params {
+            new_argument = new_argument.replace(format!("{{{}}}", special_param).as_str(), special_value);
         }
-    };
 
-    Ok(())
+        new_argument
+    }).collect()
 }
 
-fn read(_: &JsValue, args: &[JsValue], _: &mut Context) -> Result<JsValue, JsValue> {
-    let file = args[0].as_string().unwrap().as_str().to_string();
-    let mut string = String::new();
-    File::open(file).unwrap().read_to_string(&mut string).into_result()?;
-
-    Ok(JsValue::String(JsString::from(string)))
+/// Downloads the installation and every parent it names from the configured repositories,
+/// blocking until done. Fails with `PermissionsRequired`, leaving the installed files as they
+/// were, while the downloaded manifests declare permissions the user has not granted. Must not
+/// be called from an async task, use `download_installation_async` there.
+pub fn download_installation(id: String, settings: &SettingManager, cancellation: &CancellationToken) -> Result<(), Box<dyn  Error>> {
+    block_on(download_installation_async(id, settings, cancellation))
 }
 
-fn write(_: &JsValue, args: &[JsValue], context: &mut Context) -> Result<JsValue, JsValue> {
-    let installation = context.global_object().get("installation", context)?.as_string().unwrap().as_str().to_string();
-    let file = format!("installation/files/{}/{}", installation, args[0].as_string().unwrap().as_str().to_string());
-    let text = args[1].as_string().unwrap().as_str().to_string();
-    File::create(file).unwrap().write_all(text.as_bytes()).into_result()?;
+pub async fn download_installation_async(id: String, settings: &SettingManager, cancellation: &CancellationToken) -> Result<(), Box<dyn  Error>> {
+    check_id(&id)?;
+    let result = download_installation_chain(&id, settings, cancellation).await;
+    if result.as_ref().is_err_and(|error| is_cancelled(error.as_ref())) {
+        emit(ProgressEvent::Cancelled { installation: id });
+    }
 
-    Ok(JsValue::Null)
+    result
 }
 
-fn to_json(_: &JsValue, args: &[JsValue], context: &mut Context) -> Result<JsValue, JsValue> {
-    let string = args[0].as_string().unwrap().as_str().to_string();
-    let json: Value = serde_json::from_str(&string).unwrap();
-
-    Ok(to_js_json_internal(json, context)?)
-}
+async fn download_installation_chain(id: &str, settings: &SettingManager, cancellation: &CancellationToken) -> Result<(), Box<dyn  Error>> {
+    let repositories = repositories(settings)?;
+    let network = Network::new(settings)?.cancellable(cancellation);
+    let trust = TrustStore::from_settings(settings);
+
+    let mut staging = Staging::new();
+    let mut sources = Vec::new();
+    let mut chain = Vec::new();
+    let mut next = Some(id.to_string());
+    while let Some(id) = next {
+        check_chain(&chain, &id)?;
+
+        let started = Instant::now();
+        emit(ProgressEvent::PhaseStarted { installation: id.clone(), phase: Phase::Download });
+        let directory = staging.directory(&id)?;
+        sources.push((id.clone(), download_installation_files(&network, &repositories, &trust, &id, &directory).await?));
+        emit(ProgressEvent::PhaseFinished { installation: id.clone(), phase: Phase::Download, elapsed: started.elapsed() });
+
+        // Stops before fetching parents when the installation cannot run on this launcher.
+        let manifest = Manifest::load_from(&directory, &id)?;
+        manifest.check_api_version()?;
+        next = manifest.parent.clone();
+        chain.push(manifest);
+    }
 
-fn to_js_json_internal(json: Value, context: &mut Context) -> Result<JsValue, JsValue> {
-    match json {
-        Value::Object(values) => {
-            let object = JsObject::new(Object::new());
-            for (key, value) in values {
-                object.set(key, to_js_json_internal(value, context)?, false, context)?;
-            }
+    // Declining keeps the installed version, so consent is asked for before replacing it.
+    let mut requests = Vec::new();
+    for manifest in chain.iter().rev() {
+        requests.extend(missing_permissions(&manifest.id, manifest)?);
+    }
+    if !requests.is_empty() {
+        return Err(Box::new(PermissionsRequired { requests }));
+    }
+    staging.commit()?;
 
-            Ok(JsValue::Object(object))
-        },
-        Value::String(string) => {
-            Ok(JsValue::String(string.into()))
-        }
-        Value::Number(number) => {
-            Ok(JsValue::Integer(number.as_i64().unwrap() as i32))
+    for (id, (repository, index, signature)) in sources {
+        record_installation_source(&id, &repository)?;
+        match signature {
+            Some(signature) => record_signature(&id, &index, &signature)?,
+            None => remove_signature(&id)?,
         }
-        _ => Err(JsValue::String(JsString::from(format!("Json type not handled: {}", json))))
     }
+
+    Ok(())
 }
 
-fn log(_: &JsValue, args: &[JsValue], _context: &mut Context) -> Result<JsValue, JsValue> {
-    let log = match &args[0] {
-        JsValue::String(string) => string.as_str().to_string(),
-        JsValue::Boolean(boolean) => if *boolean { "true" } else { "false" }.to_string(),
-        _ => "unsupported value!".to_string(),
+/// Downloads the files of `id` into `directory` once its file index is verified to be signed by
+/// a trusted publisher, returning where it came from together with the index and its signature.
+/// Unsigned installations are only downloaded from local repositories, and only when
+/// `allow_unsigned_installations` is set. They have no signature to return.
+async fn download_installation_files(network: &Network, repositories: &[Repository], trust: &TrustStore, id: &str, directory: &Path) -> Result<(Repository, Vec<u8>, Option<IndexSignature>), Box<dyn Error>> {
+    let (repository, index_bytes) = locate_installation(network, repositories, id).await?;
+    let signature = match repository.fetch(network, &format!("{}/{}", id, SIGNATURE_FILE)).await? {
+        Some(signature) => Some(IndexSignature::parse(id, &signature)?),
+        None if trust.allows_unsigned(&repository) => None,
+        None => return Err(format!("{} from {} is not signed", id, repository.location()).into()),
     };
-    println!("{}", log);
-
-    Ok(JsValue::Null)
-}
+    if let Some(signature) = &signature {
+        trust.verify(id, &index_bytes, signature).map_err(|error| format!("{} from {}: {}", id, repository.location(), error))?;
+    }
 
-fn substring(_: &JsValue, args: &[JsValue], _context: &mut Context) -> Result<JsValue, JsValue> {
-    let string = args[0].as_string().unwrap().as_str().to_string();
-    let start = args[1].as_number().unwrap() as i32;
-    let end = args[2].as_number().unwrap() as i32;
+    let index = FileIndex::parse(&index_bytes).map_err(|error| format!("{} from {}: {}", id, repository.location(), error))?;
+    if signature.is_some() && !index.is_verified() {
+        return Err(format!("{} from {} is signed but its file index lists no hashes", id, repository.location()).into());
+    }
 
-    Ok(JsValue::String(JsString::from(string.chars().skip(start as usize).take((end - start) as usize).collect::<String>())))
-}
+    let mut files = Vec::new();
+    for entry in &index.files {
+        let bytes = repository.fetch(network, &format!("{}/{}", id, entry.path)).await
+            .map_err(|error| format!("Error downloading {} of {} from {}: {}", entry.path, id, repository.location(), error))?
+            .ok_or(format!("{} is listed by {} but missing from {}", entry.path, id, repository.location()))?;
+        entry.verify(&bytes).map_err(|error| format!("Integrity check failed for {}: {}", id, error))?;
 
-fn append(_: &JsValue, args: &[JsValue], _context: &mut Context) -> Result<JsValue, JsValue> {
-    let string = args[0].as_string().unwrap().as_str().to_string();
-    let added_string = args[1].as_string().unwrap().as_str().to_string();
+        files.push((entry, bytes));
+    }
 
-    Ok(JsValue::String(JsString::from(format!("{}{}", string, added_string))))
-}
+    for (entry, bytes) in files {
+        let path = directory.join(&entry.path);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
 
-fn replace(_: &JsValue, args: &[JsValue], _context: &mut Context) -> Result<JsValue, JsValue> {
-    let string = args[0].as_string().unwrap();
-    let initial = args[1].as_string().unwrap().as_str().to_string();
-    let wanted = args[2].as_string().unwrap().as_str().to_string();
+        let mut file = File::create(path)?;
+        file.write_all(&bytes)?;
+    }
 
-    Ok(JsValue::String(JsString::from(string.replace(initial.as_str(), wanted.as_str()))))
+    Ok((repository, index_bytes, signature))
 }
 
-fn regex_capture(_: &JsValue, args: &[JsValue], _context: &mut Context) -> Result<JsValue, JsValue> {
-    let string = args[0].as_string().unwrap().as_str().to_string();
-    let regex = args[1].as_string().unwrap().as_str().to_string();
-
-    let regex = Regex::new(&regex).unwrap();
+/// Reads an installed installation and its parents. Every installation in the chain must be
+/// signed by a key in `trusted_keys` and unchanged since it was downloaded, unless it is a local
+/// installation or comes from a local repository and `allow_unsigned_installations` is set.
+pub fn parse_installation(id: String, settings: &SettingManager) -> Result<Installation, Box<dyn Error>> {
+    check_id(&id)?;
+    let trust = TrustStore::from_settings(settings);
+    let manifests = resolve_chain(&id, |id| {
+        verify_installed(id, &trust)?;
+        Manifest::load(id)
+    })?;
+    let chain: Vec<String> = manifests.iter().map(|manifest| manifest.id.clone()).collect();
+
+    let mut installation = None;
+    for (depth, manifest) in manifests.into_iter().enumerate().rev() {
+        manifest.check_api_version()?;
+
+        installation = Some(Installation {
+            parent: installation.map(Box::new),
+            id: manifest.id.clone(),
+            manifest,
+            chain: chain[depth..].to_vec(),
+        });
+    }
 
-    let captures = regex.captures(&string).unwrap().unwrap();
-    let capture = captures.get(1).unwrap();
+    Ok(installation.expect("resolved chain is never empty"))
+}
 
-    Ok(JsValue::String(JsString::from(capture.as_str())))
+/// Fails once the install or launch running the script was cancelled, so that the builtins doing
+/// lengthy work stop the script.
+pub(crate) fn check_cancelled() -> Result<(), Box<dyn Error>> {
+    match with_downloader(|downloader| downloader.cancellation().clone()) {
+        Ok(cancellation) => cancellation.check(),
+        Err(_) => Ok(()),
+    }
 }
 
-fn copy_file(_: &JsValue, args: &[JsValue], _: &mut Context) -> Result<JsValue, JsValue> {
-    let input = args[0].as_string().unwrap().as_str();
-    let output = args[1].as_string().unwrap().as_str();
+/// Runs the install scripts of the installation's chain, parents first. Once `cancellation` is
+/// cancelled the remaining downloads are dropped and the install fails with `Cancelled`, leaving
+/// no receipt for the installation that was interrupted. Fails with `PermissionsRequired` before
+/// running anything while the chain declares permissions the user has not granted.
+///
+/// Blocks until the install finished, so it must not be called from an async task. Use
+/// `install_installation_async` there.
+pub fn install_installation(installation: &Installation, settings: &SettingManager, cancellation: &CancellationToken) -> Result<(), Box<dyn Error>> {
+    check_granted(installation)?;
+    let _downloads = DownloadScope::enter(Downloader::new(settings, cancellation)?);
+    let _recording = RecordingScope::enter();
+    let _sandbox = SandboxScope::enter(installation.chain());
+    let result = run_install_script(installation);
+    if result.as_ref().is_err_and(|error| is_cancelled(error.as_ref())) {
+        emit(ProgressEvent::Cancelled { installation: installation.id.clone() });
+    }
 
-    std::fs::copy(input, output).unwrap();
+    result
+}
 
-    Ok(JsValue::Null)
+/// Runs `install_installation` on the blocking thread pool, with its downloads as tasks on the
+/// calling runtime.
+pub async fn install_installation_async(installation: Installation, settings: SettingManager, cancellation: CancellationToken) -> Result<(), Box<dyn Error>> {
+    unblock(move || install_installation(&installation, &settings, &cancellation)).await
 }
 
-pub fn install_installation(installation: &Installation) -> Result<(), Box<dyn Error>> {
+fn run_install_script(installation: &Installation) -> Result<(), Box<dyn Error>> {
     if let Some(parent) = &installation.parent {
-        install_installation(parent)?;
+        run_install_script(parent)?;
     }
 
-    let time = SystemTime::now();
-
-    let mut context = Context::new();
-
-    context.register_global_function("download", 0, download).into_result()?;
-    context.register_global_function("extract", 0, extract).into_result()?;
-    context.register_global_function("read", 0, read).into_result()?;
-    context.register_global_function("to_json", 0, to_json).into_result()?;
-    context.register_global_function("log", 0, log).into_result()?;
-    context.register_global_function("substring", 0, substring).into_result()?;
-    context.register_global_function("regex_capture", 0, regex_capture).into_result()?;
-    context.register_global_function("copy_file", 0, copy_file).into_result()?;
+    check_cancelled()?;
+    let started = Instant::now();
+    emit(ProgressEvent::PhaseStarted { installation: installation.id.clone(), phase: Phase::Install });
 
-    context.register_global_property("installation", JsValue::String(JsString::from(installation.id.as_str())), Attribute::all());
-    context.register_global_property("os", JsValue::String(OS.into()), Attribute::all());
-    context.register_global_property("files", format!("installation/files/{}", installation.id), Attribute::all());
+    let mut context = new_context()?;
+    run_script(&mut context, installation, "install")?;
 
-    if let Some(script) = installation.get_script("install".to_string()) {
-        match context.eval(read_to_string(format!("installation/files/{}/{}", installation.id, script))?) {
-            Ok(_) => (),
-            Err(error) => return Err(error.display().to_string().into())
-        };
-    }
-
-    while THREAD_COUNT.load(Ordering::Relaxed) > 0 {
-        sleep(Duration::from_millis(500))
-    }
+    let mut files = with_downloader(Downloader::take_written)?;
+    files.extend(take_recorded());
+    Receipt::new(&installation.manifest, files).save(&installation.id)?;
 
-    println!("{}", time.elapsed().unwrap().as_millis());
+    emit(ProgressEvent::PhaseFinished { installation: installation.id.clone(), phase: Phase::Install, elapsed: started.elapsed() });
 
     Ok(())
 }
@@ -378,6 +373,28 @@ fn get_settings_value(settings: &SettingManager, context: &mut Context) -> Resul
     Ok(JsValue::Object(object))
 }
 
+fn apply_setting_declarations(manifest: &Manifest, settings: &SettingManager, context: &mut Context) -> Result<(), Box<dyn Error>> {
+    let object = context.global_object().get("settings", context).into_result()?;
+    let object = object.as_object().ok_or("settings is not an object")?;
+
+    for (id, declaration) in &manifest.settings {
+        if settings.get_setting(id.clone()).is_some() {
+            continue;
+        }
+
+        let value = match &declaration.default {
+            Value::Bool(value) => JsValue::Boolean(*value),
+            Value::Number(number) => JsValue::Integer(number.as_i64().ok_or("setting default is not an integer")? as i32),
+            Value::String(string) => JsValue::String(string.clone().into()),
+            Value::Array(array) => JsValue::String(array.iter().filter_map(Value::as_str).collect::<Vec<&str>>().join(",").into()),
+            _ => JsValue::Null,
+        };
+        object.set(id.as_str(), value, false, context).into_result()?;
+    }
+
+    Ok(())
+}
+
 struct LaunchSetup {
     main_class: Option<String>,
     classpath: Vec<String>,
@@ -456,21 +473,8 @@ fn run_launch_script(installation: &Installation, settings: &SettingManager) ->
     let mut context = match &installation.parent {
         Some(parent) => run_launch_script(parent, settings)?,
         None => {
-            let mut context = Context::new();
-
-            context.register_global_function("download", 0, download).into_result()?;
-            context.register_global_function("extract", 0, extract).into_result()?;
-            context.register_global_function("read", 0, read).into_result()?;
-            context.register_global_function("write", 0, write).into_result()?;
-            context.register_global_function("to_json", 0, to_json).into_result()?;
-            context.register_global_function("log", 0, log).into_result()?;
-            context.register_global_function("substring", 0, substring).into_result()?;
-            context.register_global_function("append", 0, append).into_result()?;
-            context.register_global_function("replace", 0, replace).into_result()?;
-            context.register_global_function("regex_capture", 0, regex_capture).into_result()?;
-            context.register_global_function("copy_file", 0, copy_file).into_result()?;
-        
-            context.register_global_property("os", JsValue::String(OS.into()), Attribute::all());
+            let mut context = new_context()?;
+
             let settings_value = get_settings_value(settings, &mut context)?;
             context.register_global_property("settings", settings_value, Attribute::all());
             context.register_global_property("root", current_dir()?.canonicalize()?.to_str().unwrap(), Attribute::all());
@@ -488,15 +492,17 @@ fn run_launch_script(installation: &Installation, settings: &SettingManager) ->
         },
     };
     
-    context.register_global_property("installation", JsValue::String(JsString::from(installation.id.as_str())), Attribute::all());
-    context.register_global_property("files", format!("installation/files/{}", installation.id), Attribute::all());
+    apply_setting_declarations(&installation.manifest, settings, &mut context)?;
 
-    if let Some(script) = installation.get_script("launch".to_string()) {
-        match context.eval(read_to_string(format!("installation/files/{}/{}", installation.id, script))?) {
-            Ok(_) => (),
-            Err(error) => return Err(error.display().to_string().into())
-        };
-    }
+    let started = Instant::now();
+    emit(ProgressEvent::PhaseStarted { installation: installation.id.clone(), phase: Phase::Launch });
+    run_script(&mut context, installation, "launch")?;
+
+    // Kept so garbage collection leaves what the launch script wrote alone.
+    let mut files = with_downloader(Downloader::take_written)?;
+    files.extend(take_recorded());
+    Receipt::record_launch(&installation.id, files)?;
+    emit(ProgressEvent::PhaseFinished { installation: installation.id.clone(), phase: Phase::Launch, elapsed: started.elapsed() });
 
     Ok(context)
 }
@@ -540,8 +546,22 @@ fn find_java_executable(wanted_version: u16) -> Result<String, Box<dyn Error>> {
     Ok("java".to_string())
 }
 
+/// Runs `run_installation` on the blocking thread pool, with the downloads of the launch
+/// scripts as tasks on the calling runtime.
+pub async fn run_installation_async(installation: Installation, arguments: RunArguments, settings: SettingManager) -> Result<(), Box<dyn Error>> {
+    unblock(move || run_installation(&installation, arguments, &settings)).await
+}
+
+/// Runs the launch scripts of the installation's chain and starts the game with the resulting
+/// arguments. Blocks while the scripts run, so it must not be called from an async task.
 pub fn run_installation(installation: &Installation, arguments: RunArguments, settings: &SettingManager) -> Result<(), Box<dyn Error>> {
-    let launch_setup: LaunchSetup = run_launch_script(installation, settings)?.into();
+    check_granted(installation)?;
+    let launch_setup: LaunchSetup = {
+        let _downloads = DownloadScope::enter(Downloader::new(settings, &CancellationToken::new())?);
+        let _recording = RecordingScope::enter();
+        let _sandbox = SandboxScope::enter(installation.chain());
+        run_launch_script(installation, settings)?.into()
+    };
 
     /*let java_executable = match settings.get_setting("java_executable".into()).unwrap() {
         Setting::String(string) => string,
@@ -0,0 +1,478 @@
+use std::{collections::HashMap, error::Error, fmt, fs::read_to_string, path::{Component, Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::installation_directory;
+
+/// The version of the script API this launcher provides, which scripts read from
+/// `launcher.api_version` and manifests can require with `api_version`. Version 2 gives install
+/// and launch scripts the same builtins, adds `execute` and the `launcher` object, and makes
+/// `extract` return what it extracted.
+pub const LAUNCHER_API_VERSION: u32 = 2;
+pub const MAX_PARENT_DEPTH: usize = 16;
+
+const KNOWN_FIELDS: [&str; 8] = ["id", "name", "version", "parent", "scripts", "api_version", "settings", "permissions"];
+const KNOWN_SCRIPTS: [&str; 2] = ["install", "launch"];
+const SETTING_TYPES: [&str; 4] = ["boolean", "integer", "string", "string_array"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub parent: Option<String>,
+    #[serde(default)]
+    pub scripts: HashMap<String, String>,
+    #[serde(default)]
+    pub api_version: Option<u32>,
+    #[serde(default)]
+    pub settings: HashMap<String, SettingDeclaration>,
+    #[serde(default)]
+    pub permissions: Permissions,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SettingDeclaration {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub default: Value,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Permissions {
+    #[serde(default)]
+    pub network: Vec<String>,
+    #[serde(default)]
+    pub write: Vec<String>,
+    #[serde(default)]
+    pub processes: bool,
+}
+
+/// Whether `id` can name an installation: a single plain path component, so that joining it onto
+/// a directory never leaves that directory.
+pub fn is_valid_id(id: &str) -> bool {
+    let mut components = Path::new(id).components();
+    !id.contains(['/', '\\']) && matches!((components.next(), components.next()), (Some(Component::Normal(_)), None))
+}
+
+/// Whether `path` names a file inside the installation directory: relative and without `..`.
+fn is_valid_script_path(path: &str) -> bool {
+    let path = Path::new(path);
+    path.components().any(|component| matches!(component, Component::Normal(_)))
+        && path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Fails unless `id` is a valid installation id. Ids come from users and manifests and are used
+/// as directory names, so every entry point taking one checks it before touching the disk.
+pub fn check_id(id: &str) -> Result<(), Box<dyn Error>> {
+    if !is_valid_id(id) {
+        return Err(format!("Invalid installation id \"{}\": ids must be a plain name without path separators, . or ..", id).into());
+    }
+
+    Ok(())
+}
+
+impl Manifest {
+    pub fn load(id: &str) -> Result<Manifest, Box<dyn Error>> {
+        check_id(id)?;
+        Manifest::load_from(&installation_directory(id), id)
+    }
+
+    /// Loads the manifest of `id` from an installation directory other than the installed one,
+    /// such as a download staging directory.
+    pub(crate) fn load_from(directory: &Path, id: &str) -> Result<Manifest, Box<dyn Error>> {
+        let file = directory.join("info.json");
+        let contents = read_to_string(&file).map_err(|error| format!("{}: {}", file.display(), error))?;
+        Manifest::parse(&contents, &file.display().to_string(), id)
+    }
+
+    /// Parses the manifest of `id`, naming `file` in errors. Manifests needing a newer script API
+    /// fail with the version they need rather than with the fields this launcher does not know.
+    pub(crate) fn parse(contents: &str, file: &str, id: &str) -> Result<Manifest, Box<dyn Error>> {
+        let json: Value = serde_json::from_str(contents).map_err(|error| format!("{}: {}", file, error))?;
+        if let Some(required) = json.get("api_version").and_then(Value::as_u64) {
+            let name = json.get("name").and_then(Value::as_str).unwrap_or(id);
+            check_api_version(name, required)?;
+        }
+
+        let manifest: Manifest = serde_json::from_str(contents).map_err(|error| format!("{}: {}", file, error))?;
+        if manifest.id != id {
+            return Err(format!("{}: id: expected \"{}\", found \"{}\"", file, id, manifest.id).into());
+        }
+        if let Some(parent) = &manifest.parent {
+            if !is_valid_id(parent) {
+                return Err(format!("{}: parent: \"{}\" is not a valid installation id", file, parent).into());
+            }
+        }
+        for (script, path) in &manifest.scripts {
+            if !is_valid_script_path(path) {
+                return Err(format!("{}: scripts.{}: \"{}\" is not a path inside the installation", file, script, path).into());
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    /// Fails when the installation needs a newer script API than this launcher provides.
+    pub fn check_api_version(&self) -> Result<(), Box<dyn Error>> {
+        match self.api_version {
+            Some(required) => check_api_version(self.display_name(), required as u64),
+            None => Ok(()),
+        }
+    }
+
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.id)
+    }
+}
+
+fn check_api_version(name: &str, required: u64) -> Result<(), Box<dyn Error>> {
+    if required > LAUNCHER_API_VERSION as u64 {
+        return Err(format!(
+            "{} needs launcher API version {}, but this launcher provides version {}. Update the launcher to use it",
+            name, required, LAUNCHER_API_VERSION
+        ).into());
+    }
+
+    Ok(())
+}
+
+pub(crate) fn manifest_file(id: &str) -> PathBuf {
+    installation_directory(id).join("info.json")
+}
+
+fn format_chain<'a>(ids: impl IntoIterator<Item = &'a str>) -> String {
+    ids.into_iter().collect::<Vec<&str>>().join(" → ")
+}
+
+/// Follows the `parent` links starting at `id`, returning the manifests ordered from the
+/// installation itself to its root ancestor. `load` is called once per installation in the
+/// chain, which lets downloads fetch each parent before it is read.
+pub(crate) fn resolve_chain<F>(id: &str, mut load: F) -> Result<Vec<Manifest>, Box<dyn Error>>
+where
+    F: FnMut(&str) -> Result<Manifest, Box<dyn Error>>,
+{
+    let mut chain: Vec<Manifest> = Vec::new();
+    let mut next = Some(id.to_string());
+
+    while let Some(id) = next {
+        check_chain(&chain, &id)?;
+        let manifest = load(&id)?;
+        next = manifest.parent.clone();
+        chain.push(manifest);
+    }
+
+    Ok(chain)
+}
+
+/// Fails when adding `id` to the partially resolved `chain` would close a cycle or make it
+/// deeper than `MAX_PARENT_DEPTH`.
+pub(crate) fn check_chain(chain: &[Manifest], id: &str) -> Result<(), Box<dyn Error>> {
+    check_id(id)?;
+    if chain.iter().any(|manifest| manifest.id == id) {
+        let ids = chain.iter().map(|manifest| manifest.id.as_str()).chain([id]);
+        return Err(format!("Installation parent chain contains a cycle: {}", format_chain(ids)).into());
+    }
+    if chain.len() == MAX_PARENT_DEPTH {
+        let ids = chain.iter().map(|manifest| manifest.id.as_str()).chain([id]);
+        return Err(format!("Installation parent chain is deeper than {}: {}", MAX_PARENT_DEPTH, format_chain(ids)).into());
+    }
+
+    Ok(())
+}
+
+pub(crate) fn display_chain(chain: &[String]) -> String {
+    format_chain(chain.iter().map(String::as_str))
+}
+
+#[derive(Debug)]
+pub struct ManifestProblem {
+    pub file: PathBuf,
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for ManifestProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.field.is_empty() {
+            write!(f, "{}: {}", self.file.display(), self.message)
+        } else {
+            write!(f, "{}: {}: {}", self.file.display(), self.field, self.message)
+        }
+    }
+}
+
+struct Validator {
+    file: PathBuf,
+    problems: Vec<ManifestProblem>,
+}
+
+impl Validator {
+    fn report(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.problems.push(ManifestProblem {
+            file: self.file.clone(),
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    fn string<'a>(&mut self, json: &'a Value, field: &str) -> Option<&'a str> {
+        match &json[field] {
+            Value::Null => None,
+            Value::String(string) if string.is_empty() => {
+                self.report(field, "must not be empty");
+                None
+            },
+            Value::String(string) => Some(string),
+            _ => {
+                self.report(field, "must be a string");
+                None
+            },
+        }
+    }
+
+    fn string_array(&mut self, json: &Value, field: &str) {
+        match json {
+            Value::Null => (),
+            Value::Array(values) => {
+                for (index, value) in values.iter().enumerate() {
+                    if !value.is_string() {
+                        self.report(format!("{}[{}]", field, index), "must be a string");
+                    }
+                }
+            },
+            _ => self.report(field, "must be an array of strings"),
+        }
+    }
+}
+
+/// Checks the manifest of a downloaded installation and returns every problem found, rather
+/// than stopping at the first one like `Manifest::load` does.
+pub fn validate_installation(id: &str) -> Result<Vec<ManifestProblem>, Box<dyn Error>> {
+    check_id(id)?;
+    let file = manifest_file(id);
+    let mut validator = Validator {
+        file: file.clone(),
+        problems: Vec::new(),
+    };
+
+    let contents = read_to_string(&file).map_err(|error| format!("{}: {}", file.display(), error))?;
+    let json: Value = match serde_json::from_str(&contents) {
+        Ok(json) => json,
+        Err(error) => {
+            validator.report("", format!("invalid json: {}", error));
+            return Ok(validator.problems);
+        },
+    };
+
+    let object = match json.as_object() {
+        Some(object) => object,
+        None => {
+            validator.report("", "must be a json object");
+            return Ok(validator.problems);
+        },
+    };
+
+    for key in object.keys() {
+        if !KNOWN_FIELDS.contains(&key.as_str()) {
+            validator.report(key.as_str(), "unknown field");
+        }
+    }
+
+    match validator.string(&json, "id") {
+        Some(manifest_id) if !is_valid_id(manifest_id) => {
+            validator.report("id", "must be a plain name without path separators, . or ..");
+        },
+        Some(manifest_id) if manifest_id != id => {
+            validator.report("id", format!("does not match installation directory \"{}\"", id));
+        },
+        Some(_) => (),
+        None if json["id"].is_null() => validator.report("id", "missing"),
+        None => (),
+    }
+    validator.string(&json, "name");
+    validator.string(&json, "version");
+    match validator.string(&json, "parent") {
+        Some(parent) if !is_valid_id(parent) => validator.report("parent", "must be a plain name without path separators, . or .."),
+        Some(parent) if parent == id => validator.report("parent", "installation cannot be its own parent"),
+        _ => (),
+    }
+
+    match &json["scripts"] {
+        Value::Null => (),
+        Value::Object(scripts) => {
+            for (script, location) in scripts {
+                let field = format!("scripts.{}", script);
+                if !KNOWN_SCRIPTS.contains(&script.as_str()) {
+                    validator.report(field.as_str(), format!("unknown script, expected one of: {}", KNOWN_SCRIPTS.join(", ")));
+                }
+                match location.as_str() {
+                    Some(location) if !is_valid_script_path(location) => {
+                        validator.report(field, "must be a relative path inside the installation without ..");
+                    },
+                    Some(location) if !installation_directory(id).join(location).is_file() => {
+                        validator.report(field, format!("script file \"{}\" does not exist", location));
+                    },
+                    Some(_) => (),
+                    None => validator.report(field, "must be a string"),
+                }
+            }
+        },
+        _ => validator.report("scripts", "must be an object"),
+    }
+
+    match &json["api_version"] {
+        Value::Null => (),
+        Value::Number(number) => match number.as_u64() {
+            Some(version) if version > LAUNCHER_API_VERSION as u64 => {
+                validator.report("api_version", format!("requires launcher api {}, this launcher provides {}", version, LAUNCHER_API_VERSION));
+            },
+            Some(_) => (),
+            None => validator.report("api_version", "must be a positive integer"),
+        },
+        _ => validator.report("api_version", "must be a positive integer"),
+    }
+
+    match &json["settings"] {
+        Value::Null => (),
+        Value::Object(settings) => {
+            for (setting, declaration) in settings {
+                let field = format!("settings.{}", setting);
+                let declaration = match declaration.as_object() {
+                    Some(declaration) => declaration,
+                    None => {
+                        validator.report(field, "must be an object with \"type\" and \"default\"");
+                        continue;
+                    },
+                };
+                for key in declaration.keys() {
+                    if key != "type" && key != "default" {
+                        validator.report(format!("{}.{}", field, key), "unknown field");
+                    }
+                }
+
+                let default = declaration.get("default").unwrap_or(&Value::Null);
+                let matches = match declaration.get("type").and_then(Value::as_str) {
+                    Some("boolean") => default.is_boolean(),
+                    Some("integer") => default.is_i64(),
+                    Some("string") => default.is_string(),
+                    Some("string_array") => default.as_array().is_some_and(|array| array.iter().all(Value::is_string)),
+                    _ => {
+                        validator.report(format!("{}.type", field), format!("must be one of: {}", SETTING_TYPES.join(", ")));
+                        continue;
+                    },
+                };
+                if !matches {
+                    validator.report(format!("{}.default", field), "missing or does not match the declared type");
+                }
+            }
+        },
+        _ => validator.report("settings", "must be an object"),
+    }
+
+    match &json["permissions"] {
+        Value::Null => (),
+        Value::Object(permissions) => {
+            for (key, value) in permissions {
+                let field = format!("permissions.{}", key);
+                match key.as_str() {
+                    "network" => {
+                        validator.string_array(value, &field);
+                        for (index, host) in value.as_array().into_iter().flatten().enumerate() {
+                            if host.as_str().is_some_and(|host| host.is_empty() || host.contains(['/', ':'])) {
+                                validator.report(format!("{}[{}]", field, index), "must be a host name, such as example.com or *.example.com");
+                            }
+                        }
+                    },
+                    "write" => {
+                        validator.string_array(value, &field);
+                        for (index, path) in value.as_array().into_iter().flatten().enumerate() {
+                            let escapes = path.as_str().is_some_and(|path| {
+                                Path::new(path).components().any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+                            });
+                            if escapes {
+                                validator.report(format!("{}[{}]", field, index), "must be a path relative to the launcher directory without ..");
+                            }
+                        }
+                    },
+                    "processes" => if !value.is_boolean() {
+                        validator.report(field, "must be a boolean");
+                    },
+                    _ => validator.report(field, "unknown permission"),
+                }
+            }
+        },
+        _ => validator.report("permissions", "must be an object"),
+    }
+
+    Ok(validator.problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rejects_scripts_outside_the_installation() {
+        for path in ["../other/install.js", "/etc/install.js", "scripts/../../install.js", ""] {
+            let contents = serde_json::json!({ "id": "base", "scripts": { "install": path } }).to_string();
+            assert!(Manifest::parse(&contents, "info.json", "base").is_err(), "{} was accepted", path);
+        }
+
+        let contents = r#"{"id": "base", "scripts": {"install": "./scripts/install.js"}}"#;
+        assert!(Manifest::parse(contents, "info.json", "base").is_ok());
+    }
+
+    /// Resolves the chain of `id` from installations given as `(id, parent)` pairs.
+    fn resolve(id: &str, installations: &[(String, Option<String>)]) -> Result<Vec<String>, Box<dyn Error>> {
+        let chain = resolve_chain(id, |id| {
+            let (_, parent) = installations.iter().find(|(installed, _)| installed == id).ok_or("not installed")?;
+            let contents = serde_json::json!({ "id": id, "parent": parent }).to_string();
+            Manifest::parse(&contents, "info.json", id)
+        })?;
+
+        Ok(chain.into_iter().map(|manifest| manifest.id).collect())
+    }
+
+    /// A chain of `length` installations where `level0` is the parent of `level1` and so on.
+    fn linear(length: usize) -> Vec<(String, Option<String>)> {
+        (0..length).map(|level| (format!("level{}", level), level.checked_sub(1).map(|parent| format!("level{}", parent)))).collect()
+    }
+
+    #[test]
+    fn resolve_chain_follows_parents() {
+        let installations = [("child".to_string(), Some("base".to_string())), ("base".to_string(), None)];
+        assert_eq!(resolve("child", &installations).unwrap(), ["child", "base"]);
+    }
+
+    #[test]
+    fn resolve_chain_rejects_installations_that_are_their_own_parent() {
+        let installations = [("base".to_string(), Some("base".to_string()))];
+        let error = resolve("base", &installations).unwrap_err();
+        assert!(error.to_string().contains("cycle"), "{}", error);
+    }
+
+    #[test]
+    fn resolve_chain_rejects_cycles() {
+        let installations = [("a".to_string(), Some("b".to_string())), ("b".to_string(), Some("a".to_string()))];
+        let error = resolve("a", &installations).unwrap_err();
+        assert!(error.to_string().contains("cycle"), "{}", error);
+    }
+
+    #[test]
+    fn resolve_chain_limits_the_depth() {
+        let top = format!("level{}", MAX_PARENT_DEPTH - 1);
+        assert_eq!(resolve(&top, &linear(MAX_PARENT_DEPTH)).unwrap().len(), MAX_PARENT_DEPTH);
+
+        let top = format!("level{}", MAX_PARENT_DEPTH);
+        let error = resolve(&top, &linear(MAX_PARENT_DEPTH + 1)).unwrap_err();
+        assert!(error.to_string().contains("deeper than"), "{}", error);
+    }
+}
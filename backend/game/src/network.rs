@@ -0,0 +1,166 @@
+use std::{error::Error, future::Future, io::{self, ErrorKind}, time::{Duration, Instant}};
+
+use rand::Rng;
+use reqwest::{Client, StatusCode};
+use settings::{Setting, SettingManager};
+use tokio::time::{sleep, timeout};
+
+use crate::cancel::{Cancelled, CancellationToken, is_cancelled};
+
+const DEFAULT_RETRIES: u32 = 3;
+const DEFAULT_CONNECT_TIMEOUT: u64 = 10;
+const DEFAULT_READ_TIMEOUT: u64 = 30;
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const CANCELLATION_POLL: Duration = Duration::from_millis(100);
+
+/// An async http client together with the retry policy every download goes through.
+///
+/// The read timeout applies to each read from the connection rather than to the whole
+/// transfer, so large files are only cut off when the server stops sending. Requests go through
+/// `read` to have it applied.
+#[derive(Clone)]
+pub(crate) struct Network {
+    client: Client,
+    retries: u32,
+    read_timeout: Duration,
+    cancellation: CancellationToken,
+}
+
+fn integer_setting(settings: &SettingManager, id: &str, default: u64) -> u64 {
+    match settings.get_setting(id.into()) {
+        Some(Setting::Integer(value)) if *value >= 0 => *value as u64,
+        _ => default,
+    }
+}
+
+impl Network {
+    pub(crate) fn new(settings: &SettingManager) -> Result<Network, Box<dyn Error>> {
+        let connect_timeout = integer_setting(settings, "download_connect_timeout", DEFAULT_CONNECT_TIMEOUT);
+        let read_timeout = integer_setting(settings, "download_read_timeout", DEFAULT_READ_TIMEOUT);
+
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(connect_timeout))
+            .build()?;
+
+        Ok(Network {
+            client,
+            retries: integer_setting(settings, "download_retries", DEFAULT_RETRIES as u64) as u32,
+            read_timeout: Duration::from_secs(read_timeout),
+            cancellation: CancellationToken::new(),
+        })
+    }
+
+    /// Makes requests and retries made through this network stop once `cancellation` is
+    /// cancelled.
+    pub(crate) fn cancellable(mut self, cancellation: &CancellationToken) -> Network {
+        self.cancellation = cancellation.clone();
+        self
+    }
+
+    pub(crate) fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Awaits a response or a chunk of one, failing with a timeout the retries treat as
+    /// transient when the server sends nothing for the read timeout. A cancellation drops the
+    /// request right away instead of waiting for the server.
+    pub(crate) async fn read<T>(&self, future: impl Future<Output = Result<T, reqwest::Error>>) -> Result<T, Box<dyn Error>> {
+        tokio::select! {
+            read = timeout(self.read_timeout, future) => match read {
+                Ok(result) => Ok(result?),
+                Err(_) => Err(Box::new(io::Error::new(ErrorKind::TimedOut, format!("nothing received for {} seconds", self.read_timeout.as_secs())))),
+            },
+            _ = self.cancelled() => Err(Box::new(Cancelled)),
+        }
+    }
+
+    /// Completes once the operation is cancelled.
+    async fn cancelled(&self) {
+        while !self.cancellation.is_cancelled() {
+            sleep(CANCELLATION_POLL).await;
+        }
+    }
+
+    pub(crate) fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Waits for `duration`, returning early with `Cancelled` when the operation is cancelled.
+    async fn sleep(&self, duration: Duration) -> Result<(), Box<dyn Error>> {
+        let until = Instant::now() + duration;
+        loop {
+            self.cancellation.check()?;
+            let now = Instant::now();
+            if now >= until {
+                return Ok(());
+            }
+            sleep((until - now).min(CANCELLATION_POLL)).await;
+        }
+    }
+
+    /// Runs `attempt` until it succeeds, retrying transient failures with exponential backoff.
+    /// When every attempt failed the error lists each of them. Cancellation is never retried and
+    /// is returned as is.
+    pub(crate) async fn retry<T, F, A>(&self, mut attempt: A) -> Result<T, Box<dyn Error>>
+    where
+        A: FnMut() -> F,
+        F: Future<Output = Result<T, Box<dyn Error>>>,
+    {
+        let mut errors = Vec::new();
+        for number in 0..=self.retries {
+            if number > 0 {
+                self.sleep(backoff(number)).await?;
+            }
+            self.cancellation.check()?;
+
+            match attempt().await {
+                Ok(value) => return Ok(value),
+                Err(error) if is_cancelled(error.as_ref()) => return Err(error),
+                Err(error) => {
+                    let transient = is_transient(error.as_ref());
+                    errors.push(error.to_string());
+                    if !transient {
+                        break;
+                    }
+                },
+            }
+        }
+
+        if errors.len() == 1 {
+            return Err(errors.remove(0).into());
+        }
+
+        let attempts: Vec<String> = errors.iter().enumerate().map(|(number, error)| format!("  attempt {}: {}", number + 1, error)).collect();
+        Err(format!("failed after {} attempts:\n{}", errors.len(), attempts.join("\n")).into())
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    let delay = BASE_BACKOFF.saturating_mul(2u32.saturating_pow(attempt - 1)).min(MAX_BACKOFF);
+    rand::thread_rng().gen_range(delay / 2..=delay)
+}
+
+fn is_transient_request(error: &reqwest::Error) -> bool {
+    if let Some(status) = error.status() {
+        return status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::REQUEST_TIMEOUT;
+    }
+
+    error.is_timeout() || error.is_connect() || error.is_request() || error.is_body()
+}
+
+fn is_transient(error: &(dyn Error + 'static)) -> bool {
+    if let Some(error) = error.downcast_ref::<reqwest::Error>() {
+        return is_transient_request(error);
+    }
+
+    if let Some(error) = error.downcast_ref::<io::Error>() {
+        if let Some(error) = error.get_ref().and_then(|inner| inner.downcast_ref::<reqwest::Error>()) {
+            return is_transient_request(error);
+        }
+
+        return matches!(error.kind(), ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::BrokenPipe | ErrorKind::TimedOut | ErrorKind::UnexpectedEof | ErrorKind::Interrupted);
+    }
+
+    false
+}
@@ -0,0 +1,327 @@
+use std::{cell::RefCell, error::Error, fmt, fs::{self, create_dir_all}, io::ErrorKind, path::{Component, Path, PathBuf}};
+
+use reqwest::Url;
+
+use crate::{Installation, Manifest, installation_directory, manifest::{Permissions, check_id}};
+
+const GRANT_DIRECTORY: &str = "installation/permissions";
+
+/// Hosts every script may download from without declaring them: the servers of the game itself.
+pub const DEFAULT_HOSTS: [&str; 6] = [
+    "launchermeta.mojang.com",
+    "launcher.mojang.com",
+    "piston-meta.mojang.com",
+    "piston-data.mojang.com",
+    "libraries.minecraft.net",
+    "resources.download.minecraft.net",
+];
+
+/// Directories every script may write to without declaring them, besides the files directory of
+/// its own installation.
+pub const DEFAULT_WRITE_ROOTS: [&str; 3] = ["libraries", "assets", "natives"];
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host.strip_suffix(domain).is_some_and(|subdomain| subdomain.ends_with('.')),
+        None => pattern == host,
+    }
+}
+
+/// A `permissions.write` entry in the form the sandbox resolves paths to, so that entries such as
+/// `./game` match the paths written below them.
+fn write_entry(entry: &str) -> PathBuf {
+    Path::new(entry).components().filter(|component| *component != Component::CurDir).collect()
+}
+
+fn writable_by_default(id: &str, path: &Path) -> bool {
+    path.starts_with(installation_directory(id)) || DEFAULT_WRITE_ROOTS.iter().any(|root| path.starts_with(root))
+}
+
+impl Permissions {
+    pub fn is_empty(&self) -> bool {
+        self.network.is_empty() && self.write.is_empty() && !self.processes
+    }
+
+    /// The part of the permissions declared by installation `id` that goes beyond what every
+    /// installation gets without asking.
+    pub fn beyond_default(&self, id: &str) -> Permissions {
+        Permissions {
+            network: self.network.iter().filter(|host| !DEFAULT_HOSTS.contains(&host.as_str())).cloned().collect(),
+            write: self.write.iter().filter(|path| !writable_by_default(id, &write_entry(path))).cloned().collect(),
+            processes: self.processes,
+        }
+    }
+
+    /// The permissions of `self` that `granted` does not include.
+    fn without(&self, granted: &Permissions) -> Permissions {
+        Permissions {
+            network: self.network.iter().filter(|host| !granted.network.contains(host)).cloned().collect(),
+            write: self.write.iter().filter(|path| !granted.write.iter().any(|granted| write_entry(granted) == write_entry(path))).cloned().collect(),
+            processes: self.processes && !granted.processes,
+        }
+    }
+
+    fn merge(&mut self, other: &Permissions) {
+        for host in &other.network {
+            if !self.network.contains(host) {
+                self.network.push(host.clone());
+            }
+        }
+        for path in &other.write {
+            if !self.write.contains(path) {
+                self.write.push(path.clone());
+            }
+        }
+        self.processes |= other.processes;
+    }
+
+    /// One line per permission, as shown when asking the user for consent.
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self.network.iter().map(|host| format!("download from {}", host)).collect();
+        lines.extend(self.write.iter().map(|path| format!("write to {}", path)));
+        if self.processes {
+            lines.push("start other programs".to_string());
+        }
+
+        lines
+    }
+}
+
+/// Permissions an installation declares beyond the default set that the user has not granted.
+#[derive(Debug, Clone)]
+pub struct PermissionRequest {
+    pub installation: String,
+    pub permissions: Permissions,
+}
+
+/// The error returned by installs and launches of installations whose permissions were not
+/// granted yet. Grant them with `grant_permissions` and try again.
+#[derive(Debug)]
+pub struct PermissionsRequired {
+    pub requests: Vec<PermissionRequest>,
+}
+
+impl fmt::Display for PermissionsRequired {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let requests: Vec<String> = self.requests.iter()
+            .map(|request| format!("{} ({})", request.installation, request.permissions.describe().join(", ")))
+            .collect();
+        write!(formatter, "Permissions not granted: {}", requests.join(", "))
+    }
+}
+
+impl Error for PermissionsRequired {}
+
+/// The permissions that are missing when `error` is the result of ungranted permissions.
+pub fn permissions_required<'a>(error: &'a (dyn Error + 'static)) -> Option<&'a PermissionsRequired> {
+    error.downcast_ref::<PermissionsRequired>()
+}
+
+fn grant_file(id: &str) -> PathBuf {
+    Path::new(GRANT_DIRECTORY).join(format!("{}.json", id))
+}
+
+/// The permissions the user granted to installation `id`.
+pub fn granted_permissions(id: &str) -> Result<Permissions, Box<dyn Error>> {
+    check_id(id)?;
+    let file = grant_file(id);
+    match fs::read_to_string(&file) {
+        Ok(contents) => Ok(serde_json::from_str(&contents).map_err(|error| format!("{}: {}", file.display(), error))?),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(Permissions::default()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Records that the user consented to `permissions` for installation `id`, in addition to the
+/// permissions granted before.
+pub fn grant_permissions(id: &str, permissions: &Permissions) -> Result<(), Box<dyn Error>> {
+    check_id(id)?;
+    let mut granted = granted_permissions(id)?;
+    granted.merge(permissions);
+
+    create_dir_all(GRANT_DIRECTORY)?;
+    fs::write(grant_file(id), serde_json::to_string_pretty(&granted)?)?;
+
+    Ok(())
+}
+
+pub(crate) fn remove_granted_permissions(id: &str) -> Result<(), Box<dyn Error>> {
+    match fs::remove_file(grant_file(id)) {
+        Ok(()) => Ok(()),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// The permissions beyond the default set that `manifest` declares and the user has not granted
+/// to installation `id`, or `None` once everything was consented to.
+pub(crate) fn missing_permissions(id: &str, manifest: &Manifest) -> Result<Option<PermissionRequest>, Box<dyn Error>> {
+    let missing = manifest.permissions.beyond_default(id).without(&granted_permissions(id)?);
+    if missing.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(PermissionRequest {
+        installation: id.to_string(),
+        permissions: missing,
+    }))
+}
+
+/// The permissions beyond the default set that `installation` or one of its parents declares and
+/// the user has not granted, parents first. Empty once everything was consented to.
+pub fn ungranted_permissions(installation: &Installation) -> Result<Vec<PermissionRequest>, Box<dyn Error>> {
+    let mut requests = match &installation.parent {
+        Some(parent) => ungranted_permissions(parent)?,
+        None => Vec::new(),
+    };
+    requests.extend(missing_permissions(&installation.id, &installation.manifest)?);
+
+    Ok(requests)
+}
+
+/// Fails with `PermissionsRequired` unless every permission of the chain was granted.
+pub(crate) fn check_granted(installation: &Installation) -> Result<(), Box<dyn Error>> {
+    let requests = ungranted_permissions(installation)?;
+    if !requests.is_empty() {
+        return Err(Box::new(PermissionsRequired { requests }));
+    }
+
+    Ok(())
+}
+
+struct Active {
+    id: String,
+    permissions: Permissions,
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<Active>> = const { RefCell::new(None) };
+}
+
+/// Limits the builtins called by the script of `installation` running on this thread to the
+/// default set and the permissions its manifest declares until dropped.
+pub(crate) struct PermissionScope;
+
+impl PermissionScope {
+    pub(crate) fn enter(installation: &Installation) -> PermissionScope {
+        let active = Active {
+            id: installation.id.clone(),
+            permissions: installation.manifest.permissions.clone(),
+        };
+        ACTIVE.with(|cell| *cell.borrow_mut() = Some(active));
+        PermissionScope
+    }
+}
+
+impl Drop for PermissionScope {
+    fn drop(&mut self) {
+        ACTIVE.with(|active| *active.borrow_mut() = None);
+    }
+}
+
+fn with_active<R>(f: impl FnOnce(&Active) -> Result<R, String>) -> Result<R, String> {
+    ACTIVE.with(|active| match active.borrow().as_ref() {
+        Some(active) => f(active),
+        None => Err("Permissions are only available to install and launch scripts".to_string()),
+    })
+}
+
+/// Fails unless the running script may download `url`.
+pub(crate) fn check_network(url: &str) -> Result<(), String> {
+    let parsed = Url::parse(url).map_err(|error| format!("Invalid url {}: {}", url, error))?;
+    let host = parsed.host_str().unwrap_or_default();
+
+    with_active(|active| {
+        if DEFAULT_HOSTS.contains(&host) || active.permissions.network.iter().any(|pattern| host_matches(pattern, host)) {
+            Ok(())
+        } else {
+            Err(format!("{} may not download from {}: it is not listed in permissions.network", active.id, host))
+        }
+    })
+}
+
+/// Fails unless the running script may write to `path`, a path resolved by the sandbox.
+pub(crate) fn check_write(path: &Path) -> Result<(), String> {
+    with_active(|active| {
+        if writable_by_default(&active.id, path) || active.permissions.write.iter().any(|allowed| path.starts_with(write_entry(allowed))) {
+            Ok(())
+        } else {
+            Err(format!("{} may not write to {}: it is not listed in permissions.write", active.id, path.display()))
+        }
+    })
+}
+
+/// Fails unless the running script may start other programs.
+pub(crate) fn check_processes() -> Result<(), String> {
+    with_active(|active| {
+        if active.permissions.processes {
+            Ok(())
+        } else {
+            Err(format!("{} may not start other programs: permissions.processes is not set", active.id))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_as<R>(id: &str, permissions: serde_json::Value, check: impl FnOnce() -> R) -> R {
+        let contents = serde_json::json!({ "id": id, "permissions": permissions }).to_string();
+        let installation = Installation {
+            parent: None,
+            id: id.to_string(),
+            manifest: Manifest::parse(&contents, "info.json", id).unwrap(),
+            chain: vec![id.to_string()],
+        };
+        let _scope = PermissionScope::enter(&installation);
+        check()
+    }
+
+    #[test]
+    fn host_wildcards_match_subdomains_only() {
+        assert!(host_matches("*.example.com", "cdn.example.com"));
+        assert!(host_matches("*.example.com", "a.b.example.com"));
+        assert!(!host_matches("*.example.com", "example.com"));
+        assert!(!host_matches("*.example.com", "evilexample.com"));
+        assert!(host_matches("example.com", "example.com"));
+        assert!(!host_matches("example.com", "cdn.example.com"));
+    }
+
+    #[test]
+    fn network_access_requires_a_listed_host() {
+        run_as("base", serde_json::json!({ "network": ["*.example.com"] }), || {
+            assert!(check_network("https://libraries.minecraft.net/a.jar").is_ok());
+            assert!(check_network("https://cdn.example.com/a.jar").is_ok());
+            assert!(check_network("https://example.org/a.jar").is_err());
+        });
+    }
+
+    #[test]
+    fn write_entries_allow_paths_below_them() {
+        run_as("base", serde_json::json!({ "write": ["./game", "mods/"] }), || {
+            assert!(check_write(Path::new("installation/files/base/info.json")).is_ok());
+            assert!(check_write(Path::new("libraries/a.jar")).is_ok());
+            assert!(check_write(Path::new("game")).is_ok());
+            assert!(check_write(Path::new("game/options.txt")).is_ok());
+            assert!(check_write(Path::new("mods/a.jar")).is_ok());
+            assert!(check_write(Path::new("gamedata/options.txt")).is_err());
+            assert!(check_write(Path::new("installation/files/other/info.json")).is_err());
+        });
+    }
+
+    #[test]
+    fn checks_require_a_scope() {
+        assert!(check_write(Path::new("libraries/a.jar")).is_err());
+        assert!(check_network("https://libraries.minecraft.net/a.jar").is_err());
+    }
+
+    #[test]
+    fn granted_write_entries_match_regardless_of_spelling() {
+        let declared = Permissions { write: vec!["./game".to_string(), "libraries/x".to_string()], ..Permissions::default() };
+        assert!(declared.beyond_default("base").write == ["./game"]);
+
+        let granted = Permissions { write: vec!["game".to_string()], ..Permissions::default() };
+        assert!(declared.beyond_default("base").without(&granted).is_empty());
+    }
+}
@@ -0,0 +1,68 @@
+use std::{sync::{Arc, Mutex}, time::Duration};
+
+/// The stage of work an installation is going through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Download,
+    Install,
+    Launch,
+}
+
+/// Something that happened while downloading, installing or launching an installation.
+///
+/// Downloads are identified by the path they are written to. The same url may be fetched to
+/// several paths at once, so it does not tell downloads apart.
+#[derive(Debug, Clone)]
+pub enum ProgressEvent {
+    PhaseStarted { installation: String, phase: Phase },
+    PhaseFinished { installation: String, phase: Phase, elapsed: Duration },
+    /// A script asked for a download. `size` is known when the script declared it.
+    FileQueued { url: String, path: String, size: Option<u64> },
+    /// `bytes` more were received, `downloaded` of `total` so far. A resumed download starts
+    /// counting at the length it resumed from.
+    BytesDownloaded { url: String, path: String, bytes: u64, downloaded: u64, total: Option<u64> },
+    /// The download is in place, either fetched or found already present.
+    FileFinished { url: String, path: String, size: u64 },
+    /// Entry `index` of `total` in `archive` is being extracted.
+    Extracting { archive: String, entry: String, index: usize, total: usize },
+    ScriptLog { installation: Option<String>, message: String },
+    /// A failure that does not stop the operation right away, such as one download of many.
+    Error { message: String },
+    /// The download or install of the installation was cancelled and stopped.
+    Cancelled { installation: String },
+}
+
+type Subscriber = Arc<dyn Fn(&ProgressEvent) + Send + Sync>;
+
+static SUBSCRIBERS: Mutex<Vec<(usize, Subscriber)>> = Mutex::new(Vec::new());
+static NEXT_ID: Mutex<usize> = Mutex::new(0);
+
+/// Keeps a subscriber registered until dropped.
+pub struct Subscription {
+    id: usize,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        SUBSCRIBERS.lock().unwrap().retain(|(id, _)| *id != self.id);
+    }
+}
+
+/// Calls `subscriber` with every event emitted while the returned subscription is alive.
+/// Events are emitted from download worker threads as well as the calling thread, so the
+/// subscriber has to synchronise any state it keeps.
+pub fn subscribe(subscriber: impl Fn(&ProgressEvent) + Send + Sync + 'static) -> Subscription {
+    let mut next_id = NEXT_ID.lock().unwrap();
+    let id = *next_id;
+    *next_id += 1;
+
+    SUBSCRIBERS.lock().unwrap().push((id, Arc::new(subscriber)));
+    Subscription { id }
+}
+
+pub(crate) fn emit(event: ProgressEvent) {
+    let subscribers: Vec<Subscriber> = SUBSCRIBERS.lock().unwrap().iter().map(|(_, subscriber)| subscriber.clone()).collect();
+    for subscriber in subscribers {
+        subscriber(&event);
+    }
+}
@@ -0,0 +1,202 @@
+use std::{cell::RefCell, error::Error, fs::{self, create_dir_all, read_dir}, io::ErrorKind, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Manifest, hash::sha1_file, manifest::check_id};
+
+pub(crate) const RECEIPT_DIRECTORY: &str = "installation/receipts";
+
+/// Every file the install script of an installation produced through `download`, `extract`,
+/// `copy_file` and `write`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Receipt {
+    /// Seconds since the unix epoch at which the install finished.
+    #[serde(default)]
+    pub installed_at: u64,
+    /// The manifest the install ran for. Updates compare against it rather than against the
+    /// installation directory, which already holds newer files when an install failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest: Option<Manifest>,
+    pub files: Vec<ReceiptFile>,
+    /// The files the launch scripts of the installation wrote the last time it was launched.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub launch_files: Vec<ReceiptFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiptFile {
+    pub path: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub sha1: String,
+    /// The store entry the file is linked to, for files downloaded with a known hash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub store: Option<String>,
+    /// How the file was produced, which is how a repair recreates it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<FileSource>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FileSource {
+    Download { url: String },
+    Extract { archive: String, entry: String },
+    Copy { from: String },
+    Write,
+}
+
+impl ReceiptFile {
+    /// Describes the file currently at `path`. `sha1` can be passed when the contents were
+    /// already verified against a known hash, to avoid reading the file again.
+    pub(crate) fn describe(path: &str, source: FileSource, store: Option<String>, sha1: Option<&str>) -> Result<ReceiptFile, Box<dyn Error>> {
+        let size = fs::metadata(path).map_err(|error| format!("{}: {}", path, error))?.len();
+        let sha1 = match sha1 {
+            Some(sha1) => sha1.to_ascii_lowercase(),
+            None => sha1_file(Path::new(path)).map_err(|error| format!("{}: {}", path, error))?,
+        };
+
+        Ok(ReceiptFile {
+            path: path.to_string(),
+            size,
+            sha1,
+            store,
+            source: Some(source),
+        })
+    }
+}
+
+/// `files` with later records of the same path replacing earlier ones, since the file was
+/// overwritten.
+fn unique(files: Vec<ReceiptFile>) -> Vec<ReceiptFile> {
+    let mut unique: Vec<ReceiptFile> = Vec::with_capacity(files.len());
+    for file in files {
+        unique.retain(|existing| existing.path != file.path);
+        unique.push(file);
+    }
+
+    unique
+}
+
+fn receipt_file(id: &str) -> PathBuf {
+    Path::new(RECEIPT_DIRECTORY).join(format!("{}.json", id))
+}
+
+impl Receipt {
+    /// A receipt for files produced just now.
+    pub(crate) fn new(manifest: &Manifest, files: Vec<ReceiptFile>) -> Receipt {
+        Receipt {
+            installed_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0),
+            manifest: Some(manifest.clone()),
+            files: unique(files),
+            launch_files: Vec::new(),
+        }
+    }
+
+    /// Replaces the launch files in the receipt of `id` with `files`. Installations without a
+    /// receipt are left alone.
+    pub(crate) fn record_launch(id: &str, files: Vec<ReceiptFile>) -> Result<(), Box<dyn Error>> {
+        if let Some(mut receipt) = Receipt::load(id)? {
+            receipt.launch_files = unique(files);
+            receipt.save(id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Every file the install and launch scripts of the installation wrote.
+    pub(crate) fn all_files(&self) -> impl Iterator<Item = &ReceiptFile> {
+        self.files.iter().chain(&self.launch_files)
+    }
+
+    /// The receipt of the last install of `id`, or `None` if it was never installed.
+    pub fn load(id: &str) -> Result<Option<Receipt>, Box<dyn Error>> {
+        check_id(id)?;
+        let file = receipt_file(id);
+        match fs::read_to_string(&file) {
+            Ok(contents) => Ok(Some(serde_json::from_str(&contents).map_err(|error| format!("{}: {}", file.display(), error))?)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(format!("{}: {}", file.display(), error).into()),
+        }
+    }
+
+    pub(crate) fn save(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        create_dir_all(RECEIPT_DIRECTORY)?;
+        fs::write(receipt_file(id), serde_json::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    pub(crate) fn remove(id: &str) -> Result<(), Box<dyn Error>> {
+        match fs::remove_file(receipt_file(id)) {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+/// Ids of every installation that has a receipt.
+pub(crate) fn receipt_ids() -> Result<Vec<String>, Box<dyn Error>> {
+    let mut ids = Vec::new();
+    let entries = match read_dir(RECEIPT_DIRECTORY) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(ids),
+        Err(error) => return Err(error.into()),
+    };
+
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "json") {
+            if let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) {
+                ids.push(id.to_string());
+            }
+        }
+    }
+    ids.sort();
+
+    Ok(ids)
+}
+
+thread_local! {
+    static RECORDED: RefCell<Option<Vec<ReceiptFile>>> = const { RefCell::new(None) };
+}
+
+/// Collects the files written by script builtins on this thread until dropped. Outside of a
+/// recording scope, such as while running launch scripts, nothing is recorded.
+pub(crate) struct RecordingScope;
+
+impl RecordingScope {
+    pub(crate) fn enter() -> RecordingScope {
+        RECORDED.with(|recorded| *recorded.borrow_mut() = Some(Vec::new()));
+        RecordingScope
+    }
+}
+
+impl Drop for RecordingScope {
+    fn drop(&mut self) {
+        RECORDED.with(|recorded| *recorded.borrow_mut() = None);
+    }
+}
+
+pub(crate) fn record(path: &Path, source: FileSource) -> Result<(), Box<dyn Error>> {
+    let recording = RECORDED.with(|recorded| recorded.borrow().is_some());
+    if !recording {
+        return Ok(());
+    }
+
+    let file = ReceiptFile::describe(&path.to_string_lossy(), source, None, None)?;
+    RECORDED.with(|recorded| {
+        if let Some(files) = recorded.borrow_mut().as_mut() {
+            files.push(file);
+        }
+    });
+
+    Ok(())
+}
+
+/// The files recorded since the last call.
+pub(crate) fn take_recorded() -> Vec<ReceiptFile> {
+    RECORDED.with(|recorded| recorded.borrow_mut().as_mut().map(std::mem::take).unwrap_or_default())
+}
@@ -0,0 +1,134 @@
+use std::{error::Error, fs::{self, create_dir_all}, io::ErrorKind, path::{Path, PathBuf}};
+
+use reqwest::StatusCode;
+use settings::{Setting, SettingManager};
+
+use crate::{manifest::is_valid_id, network::Network};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Repository {
+    Http(String),
+    Local(PathBuf),
+}
+
+impl Repository {
+    pub fn parse(location: &str) -> Result<Repository, Box<dyn Error>> {
+        let location = location.trim_end_matches('/');
+        if let Some(path) = location.strip_prefix("file://") {
+            Ok(Repository::Local(PathBuf::from(path)))
+        } else if location.starts_with("http://") || location.starts_with("https://") {
+            Ok(Repository::Http(location.to_string()))
+        } else {
+            Err(format!("Unsupported repository location: {}", location).into())
+        }
+    }
+
+    /// The location string this repository was configured with, as recorded for installed
+    /// installations.
+    pub fn location(&self) -> String {
+        match self {
+            Repository::Http(url) => url.clone(),
+            Repository::Local(path) => format!("file://{}", path.display()),
+        }
+    }
+
+    /// Fetches `path` relative to the repository root, returning `None` when the repository
+    /// does not have it.
+    pub(crate) async fn fetch(&self, network: &Network, path: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        match self {
+            Repository::Http(url) => {
+                let url = &format!("{}/{}", url, path);
+                network.retry(|| async move {
+                    let response = network.read(network.client().get(url).send()).await?;
+                    if response.status() == StatusCode::NOT_FOUND {
+                        return Ok(None);
+                    }
+
+                    let mut response = response.error_for_status()?;
+                    let mut bytes = Vec::new();
+                    while let Some(chunk) = network.read(response.chunk()).await? {
+                        bytes.extend_from_slice(&chunk);
+                    }
+
+                    Ok(Some(bytes))
+                }).await
+            },
+            Repository::Local(directory) => match fs::read(directory.join(path)) {
+                Ok(bytes) => Ok(Some(bytes)),
+                Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+                Err(error) => Err(format!("{}: {}", directory.join(path).display(), error).into()),
+            },
+        }
+    }
+}
+
+/// The configured repositories, in the order they are searched.
+pub fn repositories(settings: &SettingManager) -> Result<Vec<Repository>, Box<dyn Error>> {
+    match settings.get_setting("repositories".into()) {
+        Some(Setting::StringArray(locations)) => locations.iter().map(|location| Repository::parse(location)).collect(),
+        _ => Err("repositories setting must be a list of repository locations".into()),
+    }
+}
+
+fn source_file(id: &str) -> PathBuf {
+    Path::new("installation/sources").join(id)
+}
+
+/// The location of the repository an installed installation was downloaded from.
+pub fn installation_source(id: &str) -> Option<String> {
+    if !is_valid_id(id) {
+        return None;
+    }
+    fs::read_to_string(source_file(id)).ok().map(|location| location.trim().to_string())
+}
+
+pub(crate) fn remove_installation_source(id: &str) -> Result<(), Box<dyn Error>> {
+    match fs::remove_file(source_file(id)) {
+        Ok(()) => Ok(()),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+pub(crate) fn record_installation_source(id: &str, repository: &Repository) -> Result<(), Box<dyn Error>> {
+    create_dir_all("installation/sources")?;
+    fs::write(source_file(id), repository.location())?;
+
+    Ok(())
+}
+
+/// A repository together with a file it served.
+type Located = (Repository, Vec<u8>);
+
+/// Finds the repository serving `id`, preferring the one it was previously installed from so
+/// that updates keep coming from the same source. Returns the repository together with the
+/// installation's file index.
+pub(crate) async fn locate_installation(network: &Network, repositories: &[Repository], id: &str) -> Result<Located, Box<dyn Error>> {
+    if let Some(found) = find_installation_file(network, repositories, id, "files").await? {
+        return Ok(found);
+    }
+
+    let locations: Vec<String> = repositories.iter().map(Repository::location).collect();
+    Err(format!("Installation {} not found in any repository: {}", id, locations.join(", ")).into())
+}
+
+/// Fetches `file` of installation `id` from the first repository serving it, searched in the
+/// same order as `locate_installation`. Returns `None` when no repository has it.
+pub(crate) async fn find_installation_file(network: &Network, repositories: &[Repository], id: &str, file: &str) -> Result<Option<Located>, Box<dyn Error>> {
+    let path = format!("{}/{}", id, file);
+
+    let recorded = installation_source(id)
+        .and_then(|location| Repository::parse(&location).ok())
+        .filter(|repository| repositories.contains(repository));
+    let candidates = recorded.iter().chain(repositories.iter().filter(|repository| Some(*repository) != recorded.as_ref()));
+
+    for repository in candidates {
+        let contents = repository.fetch(network, &path).await
+            .map_err(|error| format!("Error downloading {} of {} from {}: {}", file, id, repository.location(), error))?;
+        if let Some(contents) = contents {
+            return Ok(Some((repository.clone(), contents)));
+        }
+    }
+
+    Ok(None)
+}
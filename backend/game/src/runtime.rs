@@ -0,0 +1,49 @@
+use std::{error::Error, future::Future, sync::OnceLock};
+
+use tokio::runtime::{Builder, Handle, Runtime};
+
+use crate::cancel::{Cancelled, is_cancelled};
+
+const WORKER_THREADS: usize = 2;
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+/// The runtime downloads run on: the caller's when called from within one, such as from a
+/// blocking task of an async frontend, and a small runtime owned by the crate otherwise.
+pub(crate) fn handle() -> Handle {
+    Handle::try_current().unwrap_or_else(|_| runtime().handle().clone())
+}
+
+fn runtime() -> &'static Runtime {
+    RUNTIME.get_or_init(|| {
+        Builder::new_multi_thread()
+            .worker_threads(WORKER_THREADS)
+            .thread_name("download")
+            .enable_all()
+            .build()
+            .expect("failed to start the download runtime")
+    })
+}
+
+/// Runs `future` to completion for the blocking wrappers of the async API. Like
+/// `Handle::block_on` this must not be called from an async task.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    handle().block_on(future)
+}
+
+/// Runs blocking work, such as an install script or hashing a large file, on the blocking
+/// thread pool. `Box<dyn Error>` cannot cross threads, so errors are passed back as their
+/// message, apart from cancellation which stays recognisable.
+pub(crate) async fn unblock<T, F>(work: F) -> Result<T, Box<dyn Error>>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Box<dyn Error>> + Send + 'static,
+{
+    let result = tokio::task::spawn_blocking(move || work().map_err(|error| (!is_cancelled(error.as_ref())).then(|| error.to_string()))).await?;
+
+    match result {
+        Ok(value) => Ok(value),
+        Err(Some(message)) => Err(message.into()),
+        Err(None) => Err(Box::new(Cancelled)),
+    }
+}
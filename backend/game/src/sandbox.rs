@@ -0,0 +1,149 @@
+use std::{cell::RefCell, env::current_dir, fs::symlink_metadata, path::{Component, Path, PathBuf}};
+
+use crate::installation_directory;
+
+/// Directories next to `installation` that every script may use: the game directory and the
+/// stores of libraries, assets and natives shared between installations.
+pub const SHARED_ROOTS: [&str; 4] = ["game", "libraries", "assets", "natives"];
+
+thread_local! {
+    static ROOTS: RefCell<Option<Vec<PathBuf>>> = const { RefCell::new(None) };
+}
+
+/// Confines the filesystem builtins of scripts running on this thread to the files of the
+/// installations in `chain` and the shared roots until dropped.
+pub(crate) struct SandboxScope;
+
+impl SandboxScope {
+    pub(crate) fn enter(chain: &[String]) -> SandboxScope {
+        let roots = chain.iter().map(|id| installation_directory(id)).chain(SHARED_ROOTS.iter().map(PathBuf::from)).collect();
+        ROOTS.with(|roots_cell| *roots_cell.borrow_mut() = Some(roots));
+        SandboxScope
+    }
+}
+
+impl Drop for SandboxScope {
+    fn drop(&mut self) {
+        ROOTS.with(|roots| *roots.borrow_mut() = None);
+    }
+}
+
+/// Resolves a path given to a script builtin to the path relative to the launcher directory it
+/// refers to, failing with an error for the script when it is not inside one of the allowed
+/// roots. Absolute paths are accepted when they point inside the launcher directory, since
+/// launch scripts build paths from `root`.
+pub(crate) fn resolve(path: &str) -> Result<PathBuf, String> {
+    let roots = ROOTS.with(|roots| roots.borrow().clone())
+        .ok_or_else(|| format!("Access to {} denied: files are only accessible from install and launch scripts", path))?;
+
+    let given = Path::new(path);
+    let relative = if given.is_absolute() {
+        let base = current_dir().and_then(|directory| directory.canonicalize()).map_err(|error| error.to_string())?;
+        given.strip_prefix(&base).map_err(|_| format!("Access to {} denied: it is outside the launcher directory", path))?.to_path_buf()
+    } else {
+        given.to_path_buf()
+    };
+
+    let mut normalized = PathBuf::new();
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => (),
+            Component::ParentDir => return Err(format!("Access to {} denied: paths may not contain ..", path)),
+            Component::RootDir | Component::Prefix(_) => return Err(format!("Access to {} denied: it is outside the launcher directory", path)),
+        }
+    }
+
+    let root = roots.iter().find(|root| normalized.starts_with(root)).ok_or_else(|| {
+        let allowed: Vec<String> = roots.iter().map(|root| root.display().to_string()).collect();
+        format!("Access to {} denied: scripts may only access files in {}", path, allowed.join(", "))
+    })?;
+    check_links(root, &normalized).map_err(|reason| format!("Access to {} denied: {}", path, reason))?;
+
+    Ok(normalized)
+}
+
+/// Rejects paths that leave `root` through a symbolic link, by resolving the deepest part of
+/// the path that already exists. The error explains which link escapes.
+pub(crate) fn check_links(root: &Path, path: &Path) -> Result<(), String> {
+    let canonical_root = match root.canonicalize() {
+        Ok(canonical_root) => canonical_root,
+        // Nothing below a root that does not exist yet can be a link.
+        Err(_) => return Ok(()),
+    };
+
+    let existing = match path.ancestors().find(|ancestor| symlink_metadata(ancestor).is_ok()) {
+        Some(existing) => existing,
+        None => return Ok(()),
+    };
+    let target = existing.canonicalize().map_err(|_| format!("{} is a broken symbolic link", existing.display()))?;
+    if !target.starts_with(&canonical_root) {
+        return Err(format!("{} links to {}, outside {}", existing.display(), target.display(), root.display()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    fn resolve_in(chain: &[&str], path: &str) -> Result<PathBuf, String> {
+        let chain: Vec<String> = chain.iter().map(|id| id.to_string()).collect();
+        let _scope = SandboxScope::enter(&chain);
+        resolve(path)
+    }
+
+    #[test]
+    fn resolves_paths_inside_roots() {
+        assert_eq!(resolve_in(&["base"], "libraries/a.jar"), Ok(PathBuf::from("libraries/a.jar")));
+        assert_eq!(resolve_in(&["base"], "./installation/files/base/info.json"), Ok(PathBuf::from("installation/files/base/info.json")));
+
+        let absolute = current_dir().unwrap().canonicalize().unwrap().join("assets/index.json");
+        assert_eq!(resolve_in(&["base"], &absolute.to_string_lossy()), Ok(PathBuf::from("assets/index.json")));
+    }
+
+    #[test]
+    fn rejects_parent_directories() {
+        assert!(resolve_in(&["base"], "libraries/../installation/files/other/info.json").is_err());
+        assert!(resolve_in(&["base"], "../outside").is_err());
+    }
+
+    #[test]
+    fn rejects_absolute_paths_outside_the_launcher_directory() {
+        assert!(resolve_in(&["base"], "/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn rejects_paths_just_outside_a_root() {
+        assert!(resolve_in(&["evil"], "installation/files/evil2/info.json").is_err());
+        assert!(resolve_in(&["base"], "libraries2/a.jar").is_err());
+        assert!(resolve_in(&["base"], "installation/receipts/base.json").is_err());
+    }
+
+    #[test]
+    fn requires_a_scope() {
+        assert!(resolve("libraries/a.jar").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_links_escaping_a_root() {
+        let directory = env::temp_dir().join(format!("game-sandbox-{}", process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        let root = directory.join("root");
+        fs::create_dir_all(root.join("inside")).unwrap();
+        fs::create_dir_all(directory.join("outside")).unwrap();
+        std::os::unix::fs::symlink(directory.join("outside"), root.join("escape")).unwrap();
+        std::os::unix::fs::symlink("inside", root.join("alias")).unwrap();
+
+        assert!(check_links(&root, &root.join("escape/file")).is_err());
+        assert!(check_links(&root, &root.join("escape")).is_err());
+        assert!(check_links(&root, &root.join("alias/file")).is_ok());
+        assert!(check_links(&root, &root.join("new/file")).is_ok());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
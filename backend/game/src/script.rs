@@ -0,0 +1,279 @@
+use std::{error::Error, fs::{File, read_to_string}, io::{Read, Write, copy}, path::Path, process::Command};
+
+use boa::{Context, JsString, JsValue, builtins::function::NativeFunction, object::{JsObject, Object}, property::Attribute};
+use fancy_regex::Regex;
+use serde_json::Value;
+
+use crate::{Installation, IntoResult, LAUNCHER_API_VERSION, OS, archive::extract_zip, check_cancelled, downloader::{Downloader, with_downloader}, hash::Checksum, permission::{PermissionScope, check_network, check_processes, check_write}, progress::{ProgressEvent, emit}, receipt::{FileSource, record}, sandbox::resolve, store::create_unlinked};
+
+/// The builtins of the script API, the same for install and launch scripts.
+const BUILTINS: [(&str, NativeFunction); 12] = [
+    ("download", download),
+    ("extract", extract),
+    ("read", read),
+    ("write", write),
+    ("to_json", to_json),
+    ("log", log),
+    ("substring", substring),
+    ("append", append),
+    ("replace", replace),
+    ("regex_capture", regex_capture),
+    ("copy_file", copy_file),
+    ("execute", execute),
+];
+
+/// Creates a context providing the script API: the builtins, `os`, and `launcher`, whose
+/// `api_version` is the `LAUNCHER_API_VERSION` of this launcher.
+pub(crate) fn new_context() -> Result<Context, Box<dyn Error>> {
+    let mut context = Context::new();
+    for (name, builtin) in BUILTINS {
+        context.register_global_function(name, 0, builtin).into_result()?;
+    }
+
+    let launcher = JsObject::new(Object::new());
+    launcher.set("api_version", JsValue::Integer(LAUNCHER_API_VERSION as i32), false, &mut context).into_result()?;
+    context.register_global_property("launcher", JsValue::Object(launcher), Attribute::all());
+    context.register_global_property("os", JsValue::String(OS.into()), Attribute::all());
+
+    Ok(context)
+}
+
+/// Runs the `name` script of `installation` in `context` with `installation` and `files` set to
+/// it and the builtins limited to its permissions, then waits for the downloads it queued.
+/// Does nothing but set the globals when the installation has no such script.
+pub(crate) fn run_script(context: &mut Context, installation: &Installation, name: &str) -> Result<(), Box<dyn Error>> {
+    context.register_global_property("installation", JsValue::String(JsString::from(installation.id.as_str())), Attribute::all());
+    context.register_global_property("files", format!("installation/files/{}", installation.id), Attribute::all());
+
+    let script = match installation.get_script(name.to_string()) {
+        Some(script) => script,
+        None => return Ok(()),
+    };
+
+    let _permissions = PermissionScope::enter(installation);
+    let result = context.eval(read_to_string(format!("installation/files/{}/{}", installation.id, script))?);
+    let downloads = with_downloader(Downloader::wait).and_then(|result| result);
+    check_cancelled()?;
+    if let Err(error) = result {
+        return Err(error.display().to_string().into());
+    }
+
+    downloads
+}
+
+/// Reads the optional third argument of `download`, which is either the legacy
+/// `single_thread` boolean or an object with `single_thread`, `size`, `sha1` and `sha256`.
+fn download_options(argument: Option<&JsValue>, context: &mut Context) -> Result<(bool, Checksum), JsValue> {
+    let options = match argument {
+        None | Some(JsValue::Undefined) => return Ok((false, Checksum::default())),
+        Some(JsValue::Boolean(single_thread)) => return Ok((*single_thread, Checksum::default())),
+        Some(JsValue::Object(options)) => options,
+        Some(_) => return Err("Invalid argument for download".into()),
+    };
+
+    let single_thread = match options.get("single_thread", context)? {
+        JsValue::Undefined => false,
+        value => value.as_boolean().ok_or("Invalid single_thread for download")?,
+    };
+    let size = match options.get("size", context)? {
+        JsValue::Undefined => None,
+        value => Some(value.as_number().filter(|size| *size >= 0.0).ok_or("Invalid size for download")? as u64),
+    };
+    let mut hash = |key: &str| -> Result<Option<String>, JsValue> {
+        match options.get(key, context)? {
+            JsValue::Undefined => Ok(None),
+            value => Ok(Some(value.as_string().ok_or(format!("Invalid {} for download", key))?.to_string())),
+        }
+    };
+
+    Ok((single_thread, Checksum {
+        size,
+        sha256: hash("sha256")?,
+        sha1: hash("sha1")?,
+    }))
+}
+
+/// Resolves a path given to a builtin through the sandbox of the running script.
+fn script_path(path: &str) -> Result<String, JsValue> {
+    Ok(resolve(path)?.to_string_lossy().into_owned())
+}
+
+/// Resolves a path a builtin writes to, failing unless the running script may write there.
+fn script_write_path(path: &str) -> Result<String, JsValue> {
+    let resolved = resolve(path)?;
+    check_write(&resolved)?;
+
+    Ok(resolved.to_string_lossy().into_owned())
+}
+
+fn download(_: &JsValue, args: &[JsValue], context: &mut Context) -> Result<JsValue, JsValue> {
+    check_cancelled().map_err(|error| error.to_string())?;
+    let url = args[0].as_string().ok_or("Invalid argument for download")?.to_string().clone();
+    check_network(&url)?;
+    let path = script_write_path(args[1].as_string().ok_or("Invalid argument for download")?.as_str())?;
+    let (single_thread, expected) = download_options(args.get(2), context)?;
+
+    let result = with_downloader(|downloader| {
+        if single_thread {
+            downloader.download_now(&url, &path, &expected)
+        } else {
+            downloader.queue(url.clone(), path, expected);
+            Ok(())
+        }
+    }).and_then(|result| result);
+
+    match result {
+        Ok(()) => Ok(JsValue::Null),
+        Err(error) => Err(JsValue::String(JsString::from(format!("Error downloading {}: {}", url, error)))),
+    }
+}
+
+fn extract(_: &JsValue, args: &[JsValue], context: &mut Context) -> Result<JsValue, JsValue> {
+    check_cancelled().map_err(|error| error.to_string())?;
+    let zip = &script_path(args[0].as_string().unwrap().as_str())?;
+    let location = &script_write_path(args[1].as_string().unwrap().as_str())?;
+    match extract_zip(zip.as_str(), location.as_str()) {
+        Ok(extracted) => {
+            let object = JsObject::new(Object::new());
+            object.set("files", JsValue::Integer(extracted.files as i32), false, context)?;
+            object.set("bytes", JsValue::Rational(extracted.bytes as f64), false, context)?;
+
+            Ok(JsValue::Object(object))
+        },
+        Err(e) => Err(format!("Error extracting file {} to {}: {}", zip, location, e).into()),
+    }
+}
+
+fn read(_: &JsValue, args: &[JsValue], _: &mut Context) -> Result<JsValue, JsValue> {
+    let file = script_path(args[0].as_string().unwrap().as_str())?;
+    let mut string = String::new();
+    File::open(&file).and_then(|mut opened| opened.read_to_string(&mut string))
+        .map_err(|error| format!("Error reading {}: {}", file, error))?;
+
+    Ok(JsValue::String(JsString::from(string)))
+}
+
+fn write(_: &JsValue, args: &[JsValue], context: &mut Context) -> Result<JsValue, JsValue> {
+    let installation = context.global_object().get("installation", context)?.as_string().unwrap().as_str().to_string();
+    let file = script_write_path(&format!("installation/files/{}/{}", installation, args[0].as_string().unwrap().as_str()))?;
+    let text = args[1].as_string().unwrap().as_str().to_string();
+    create_unlinked(&file).and_then(|mut created| created.write_all(text.as_bytes()))
+        .map_err(|error| format!("Error writing {}: {}", file, error))?;
+    record(Path::new(&file), FileSource::Write).map_err(|error| error.to_string())?;
+
+    Ok(JsValue::Null)
+}
+
+fn to_json(_: &JsValue, args: &[JsValue], context: &mut Context) -> Result<JsValue, JsValue> {
+    let string = args[0].as_string().unwrap().as_str().to_string();
+    let json: Value = serde_json::from_str(&string).unwrap();
+
+    Ok(to_js_json_internal(json, context)?)
+}
+
+fn to_js_json_internal(json: Value, context: &mut Context) -> Result<JsValue, JsValue> {
+    match json {
+        Value::Object(values) => {
+            let object = JsObject::new(Object::new());
+            for (key, value) in values {
+                object.set(key, to_js_json_internal(value, context)?, false, context)?;
+            }
+
+            Ok(JsValue::Object(object))
+        },
+        Value::String(string) => {
+            Ok(JsValue::String(string.into()))
+        }
+        Value::Number(number) => {
+            Ok(JsValue::Integer(number.as_i64().unwrap() as i32))
+        }
+        _ => Err(JsValue::String(JsString::from(format!("Json type not handled: {}", json))))
+    }
+}
+
+fn log(_: &JsValue, args: &[JsValue], context: &mut Context) -> Result<JsValue, JsValue> {
+    let log = match &args[0] {
+        JsValue::String(string) => string.as_str().to_string(),
+        JsValue::Boolean(boolean) => if *boolean { "true" } else { "false" }.to_string(),
+        _ => "unsupported value!".to_string(),
+    };
+    let installation = context.global_object().get("installation", context)?.as_string().map(|installation| installation.to_string());
+    emit(ProgressEvent::ScriptLog { installation, message: log });
+
+    Ok(JsValue::Null)
+}
+
+fn substring(_: &JsValue, args: &[JsValue], _context: &mut Context) -> Result<JsValue, JsValue> {
+    let string = args[0].as_string().unwrap().as_str().to_string();
+    let start = args[1].as_number().unwrap() as i32;
+    let end = args[2].as_number().unwrap() as i32;
+
+    Ok(JsValue::String(JsString::from(string.chars().skip(start as usize).take((end - start) as usize).collect::<String>())))
+}
+
+fn append(_: &JsValue, args: &[JsValue], _context: &mut Context) -> Result<JsValue, JsValue> {
+    let string = args[0].as_string().unwrap().as_str().to_string();
+    let added_string = args[1].as_string().unwrap().as_str().to_string();
+
+    Ok(JsValue::String(JsString::from(format!("{}{}", string, added_string))))
+}
+
+fn replace(_: &JsValue, args: &[JsValue], _context: &mut Context) -> Result<JsValue, JsValue> {
+    let string = args[0].as_string().unwrap();
+    let initial = args[1].as_string().unwrap().as_str().to_string();
+    let wanted = args[2].as_string().unwrap().as_str().to_string();
+
+    Ok(JsValue::String(JsString::from(string.replace(initial.as_str(), wanted.as_str()))))
+}
+
+fn regex_capture(_: &JsValue, args: &[JsValue], _context: &mut Context) -> Result<JsValue, JsValue> {
+    let string = args[0].as_string().unwrap().as_str().to_string();
+    let regex = args[1].as_string().unwrap().as_str().to_string();
+
+    let regex = Regex::new(&regex).unwrap();
+
+    let captures = regex.captures(&string).unwrap().unwrap();
+    let capture = captures.get(1).unwrap();
+
+    Ok(JsValue::String(JsString::from(capture.as_str())))
+}
+
+fn copy_file(_: &JsValue, args: &[JsValue], _: &mut Context) -> Result<JsValue, JsValue> {
+    check_cancelled().map_err(|error| error.to_string())?;
+    let input = script_path(args[0].as_string().unwrap().as_str())?;
+    let output = script_write_path(args[1].as_string().unwrap().as_str())?;
+
+    let mut input_file = File::open(&input).map_err(|error| format!("Error reading {}: {}", input, error))?;
+    let mut output_file = create_unlinked(&output).map_err(|error| format!("Error writing {}: {}", output, error))?;
+    copy(&mut input_file, &mut output_file).map_err(|error| format!("Error copying {} to {}: {}", input, output, error))?;
+    record(Path::new(&output), FileSource::Copy { from: input }).map_err(|error| error.to_string())?;
+
+    Ok(JsValue::Null)
+}
+
+fn execute(_: &JsValue, args: &[JsValue], context: &mut Context) -> Result<JsValue, JsValue> {
+    check_cancelled().map_err(|error| error.to_string())?;
+    check_processes()?;
+    let program = args[0].as_string().ok_or("Invalid argument for execute")?.to_string();
+    // Programs given as a path must be inside the sandbox, bare names are looked up on the PATH.
+    let program = if program.contains('/') || program.contains('\\') {
+        script_path(&program)?
+    } else {
+        program
+    };
+
+    let mut arguments = Vec::new();
+    if let Some(array) = args.get(1).and_then(JsValue::as_object) {
+        let length = array.get("length", context)?.to_u32(context)?;
+        for index in 0..length {
+            arguments.push(array.get(index, context)?.to_string(context)?.to_string());
+        }
+    }
+
+    let output = Command::new(&program).args(&arguments).output().map_err(|error| format!("Error starting {}: {}", program, error))?;
+    if !output.status.success() {
+        return Err(format!("{} failed with {}: {}", program, output.status, String::from_utf8_lossy(&output.stderr).trim()).into());
+    }
+
+    Ok(JsValue::String(JsString::from(String::from_utf8_lossy(&output.stdout).into_owned())))
+}
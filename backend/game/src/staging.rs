@@ -0,0 +1,113 @@
+use std::{error::Error, fs::{create_dir_all, remove_dir_all, rename}, path::{Path, PathBuf}};
+
+use crate::{installation_directory, manifest::check_id};
+
+const STAGING_DIRECTORY: &str = "installation/staging";
+const BACKUP_DIRECTORY: &str = "installation/backup";
+
+/// Installation files downloaded into a temporary directory, only moved into
+/// `installation/files` once every installation in the chain downloaded successfully.
+/// Dropping an uncommitted staging removes what was downloaded and leaves the existing
+/// installations untouched.
+pub(crate) struct Staging {
+    ids: Vec<String>,
+    committed: bool,
+}
+
+impl Staging {
+    pub(crate) fn new() -> Staging {
+        Staging {
+            ids: Vec::new(),
+            committed: false,
+        }
+    }
+
+    /// Creates an empty staging directory for `id`.
+    pub(crate) fn directory(&mut self, id: &str) -> Result<PathBuf, Box<dyn Error>> {
+        check_id(id)?;
+        let directory = Path::new(STAGING_DIRECTORY).join(id);
+        if directory.exists() {
+            remove_dir_all(&directory)?;
+        }
+        create_dir_all(&directory)?;
+
+        if !self.ids.iter().any(|staged| staged == id) {
+            self.ids.push(id.to_string());
+        }
+
+        Ok(directory)
+    }
+
+    /// Swaps every staged installation into place. If any swap fails the installations that
+    /// were already swapped are restored from their backups.
+    pub(crate) fn commit(mut self) -> Result<(), Box<dyn Error>> {
+        create_dir_all("installation/files")?;
+        create_dir_all(BACKUP_DIRECTORY)?;
+
+        let mut swapped: Vec<&String> = Vec::new();
+        for id in &self.ids {
+            if let Err(error) = swap(id) {
+                for id in swapped.iter().rev() {
+                    let _ = restore(id);
+                }
+                return Err(format!("Failed to move {} into place: {}", id, error).into());
+            }
+            swapped.push(id);
+        }
+
+        for id in &self.ids {
+            let backup = Path::new(BACKUP_DIRECTORY).join(id);
+            if backup.exists() {
+                let _ = remove_dir_all(backup);
+            }
+        }
+        self.committed = true;
+
+        Ok(())
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+
+        for id in &self.ids {
+            let _ = remove_dir_all(Path::new(STAGING_DIRECTORY).join(id));
+        }
+    }
+}
+
+fn swap(id: &str) -> Result<(), Box<dyn Error>> {
+    let installed = installation_directory(id);
+    let backup = Path::new(BACKUP_DIRECTORY).join(id);
+    if backup.exists() {
+        remove_dir_all(&backup)?;
+    }
+    if installed.exists() {
+        rename(&installed, &backup)?;
+    }
+
+    if let Err(error) = rename(Path::new(STAGING_DIRECTORY).join(id), &installed) {
+        if backup.exists() {
+            rename(&backup, &installed)?;
+        }
+        return Err(error.into());
+    }
+
+    Ok(())
+}
+
+fn restore(id: &str) -> Result<(), Box<dyn Error>> {
+    let installed = installation_directory(id);
+    let backup = Path::new(BACKUP_DIRECTORY).join(id);
+    if installed.exists() {
+        remove_dir_all(&installed)?;
+    }
+    if backup.exists() {
+        rename(backup, installed)?;
+    }
+
+    Ok(())
+}
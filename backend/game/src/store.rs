@@ -0,0 +1,96 @@
+use std::{collections::HashMap, error::Error, fs::{self, File, create_dir_all, hard_link, remove_file}, io, path::{Path, PathBuf}, sync::{Arc, Mutex, OnceLock, Weak}};
+
+use tokio::sync::Mutex as EntryLock;
+
+use crate::hash::Checksum;
+
+pub(crate) const STORE_DIRECTORY: &str = "installation/store";
+
+/// The locks of entries someone is working on. A lock goes away with its last holder.
+static ENTRY_LOCKS: OnceLock<Mutex<HashMap<PathBuf, Weak<EntryLock<()>>>>> = OnceLock::new();
+
+/// Where a file with a known hash lives in the shared store, keyed by its sha1 when known and
+/// by its sha256 otherwise. Files without a hash are not stored.
+pub(crate) fn store_path(checksum: &Checksum) -> Option<PathBuf> {
+    let (algorithm, hash) = match (&checksum.sha1, &checksum.sha256) {
+        (Some(sha1), _) => ("sha1", sha1),
+        (None, Some(sha256)) => ("sha256", sha256),
+        (None, None) => return None,
+    };
+    let hash = hash.to_ascii_lowercase();
+    if hash.len() < 3 || !hash.chars().all(|character| character.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    Some(Path::new(STORE_DIRECTORY).join(algorithm).join(&hash[..2]).join(hash))
+}
+
+/// Serialises work on a single store entry, since several downloads may ask for the same
+/// file at once.
+pub(crate) fn lock_entry(entry: &Path) -> Arc<EntryLock<()>> {
+    let mut locks = ENTRY_LOCKS.get_or_init(Default::default).lock().unwrap();
+    if let Some(lock) = locks.get(entry).and_then(Weak::upgrade) {
+        return lock;
+    }
+
+    locks.retain(|_, lock| lock.strong_count() > 0);
+    let lock = Arc::new(EntryLock::new(()));
+    locks.insert(entry.to_path_buf(), Arc::downgrade(&lock));
+    lock
+}
+
+/// Whether the store entry exists and still has the expected contents. Entries that do not
+/// are removed so they get fetched again.
+pub(crate) fn entry_is_valid(entry: &Path, checksum: &Checksum) -> Result<bool, Box<dyn Error>> {
+    if !entry.is_file() {
+        return Ok(false);
+    }
+    if checksum.verify_file(entry).is_ok() {
+        return Ok(true);
+    }
+
+    remove_file(entry)?;
+    Ok(false)
+}
+
+/// Adds an existing file to the store under `entry`.
+pub(crate) fn adopt(path: &Path, entry: &Path) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = entry.parent() {
+        create_dir_all(parent)?;
+    }
+    if hard_link(path, entry).is_err() {
+        fs::copy(path, entry)?;
+    }
+
+    Ok(())
+}
+
+/// Makes `path` refer to the store entry, as a hard link where the filesystem allows it and as
+/// a copy otherwise.
+pub(crate) fn link_into(entry: &Path, path: &Path) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)?;
+    }
+    match remove_file(path) {
+        Ok(()) => (),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+        Err(error) => return Err(error.into()),
+    }
+
+    if hard_link(entry, path).is_err() {
+        fs::copy(entry, path)?;
+    }
+
+    Ok(())
+}
+
+/// Creates `path` for writing without writing through a hard link into the store.
+pub(crate) fn create_unlinked(path: impl AsRef<Path>) -> io::Result<File> {
+    match remove_file(path.as_ref()) {
+        Ok(()) => (),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+        Err(error) => return Err(error),
+    }
+
+    File::create(path)
+}
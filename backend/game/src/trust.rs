@@ -0,0 +1,202 @@
+use std::{error::Error, fs::{self, create_dir_all}, io::ErrorKind, path::{Path, PathBuf}};
+
+use ed25519_dalek::{PublicKey, Signature};
+use serde::{Deserialize, Serialize};
+use settings::{Setting, SettingManager};
+
+use crate::{index::FileIndex, installation_directory, repository::{Repository, installation_source}};
+
+/// The file next to `files` in a repository holding the publisher's signature over the index.
+pub const SIGNATURE_FILE: &str = "files.sig";
+
+const SIGNATURE_DIRECTORY: &str = "installation/signatures";
+
+/// The contents of `files.sig`: an ed25519 signature over the exact bytes of the installation's
+/// `files` index, and the public key that made it, both hex encoded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexSignature {
+    pub key: String,
+    pub signature: String,
+}
+
+/// The publisher keys installations may be signed with, from the `trusted_keys` setting, and
+/// whether installations that were never downloaded or come from a local repository may be used
+/// without a signature.
+#[derive(Debug, Clone, Default)]
+pub struct TrustStore {
+    keys: Vec<String>,
+    allow_unsigned: bool,
+}
+
+impl TrustStore {
+    pub fn from_settings(settings: &SettingManager) -> TrustStore {
+        let keys = match settings.get_setting("trusted_keys".into()) {
+            Some(Setting::StringArray(keys)) => keys.iter().map(|key| key.trim().to_lowercase()).collect(),
+            _ => Vec::new(),
+        };
+        let allow_unsigned = matches!(settings.get_setting("allow_unsigned_installations".into()), Some(Setting::Boolean(true)));
+
+        TrustStore { keys, allow_unsigned }
+    }
+
+    /// Whether installations from `repository` may be downloaded without a signature. Only local
+    /// repositories qualify, so offline copies can be used without signing them.
+    pub(crate) fn allows_unsigned(&self, repository: &Repository) -> bool {
+        self.allow_unsigned && matches!(repository, Repository::Local(_))
+    }
+
+    /// Checks that `signature` is a valid signature over `index` by a trusted key.
+    pub(crate) fn verify(&self, id: &str, index: &[u8], signature: &IndexSignature) -> Result<(), Box<dyn Error>> {
+        let key = signature.key.trim().to_lowercase();
+        if self.keys.is_empty() {
+            return Err(format!("{} is signed with key {}, but no keys are trusted: add the publisher's key to the trusted_keys setting", id, key).into());
+        }
+        if !self.keys.contains(&key) {
+            return Err(format!("{} is signed with key {}, which is not in trusted_keys", id, key).into());
+        }
+
+        let public_key = hex::decode(&key).ok().and_then(|bytes| PublicKey::from_bytes(&bytes).ok())
+            .ok_or_else(|| format!("{} is signed with an invalid key: {}", id, key))?;
+        let signature_bytes = hex::decode(signature.signature.trim()).map_err(|error| format!("{} has an invalid signature: {}", id, error))?;
+        let signature = Signature::from_bytes(&signature_bytes).map_err(|error| format!("{} has an invalid signature: {}", id, error))?;
+        public_key.verify_strict(index, &signature).map_err(|_| format!("{}: the signature does not match its file index", id))?;
+
+        Ok(())
+    }
+}
+
+impl IndexSignature {
+    pub(crate) fn parse(id: &str, bytes: &[u8]) -> Result<IndexSignature, Box<dyn Error>> {
+        Ok(serde_json::from_slice(bytes).map_err(|error| format!("{}/{}: {}", id, SIGNATURE_FILE, error))?)
+    }
+}
+
+/// What was verified when an installation was downloaded, kept to check the installed files
+/// against whenever the installation is parsed.
+#[derive(Debug, Serialize, Deserialize)]
+struct SignatureRecord {
+    index: String,
+    signature: IndexSignature,
+}
+
+fn record_file(id: &str) -> PathBuf {
+    Path::new(SIGNATURE_DIRECTORY).join(format!("{}.json", id))
+}
+
+pub(crate) fn record_signature(id: &str, index: &[u8], signature: &IndexSignature) -> Result<(), Box<dyn Error>> {
+    let record = SignatureRecord {
+        index: String::from_utf8(index.to_vec())?,
+        signature: signature.clone(),
+    };
+
+    create_dir_all(SIGNATURE_DIRECTORY)?;
+    fs::write(record_file(id), serde_json::to_string_pretty(&record)?)?;
+
+    Ok(())
+}
+
+pub(crate) fn remove_signature(id: &str) -> Result<(), Box<dyn Error>> {
+    match fs::remove_file(record_file(id)) {
+        Ok(()) => Ok(()),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Checks that installation `id` on disk is what a trusted publisher signed: the recorded index
+/// is signed by a key that is still trusted and every file it lists is unchanged. Installations
+/// without a signature are only accepted when `trust` allows unsigned installations and they
+/// were never downloaded or were downloaded from a local repository, which is meant for
+/// developing installations and using offline copies of repositories.
+pub(crate) fn verify_installed(id: &str, trust: &TrustStore) -> Result<(), Box<dyn Error>> {
+    let record: SignatureRecord = match fs::read_to_string(record_file(id)) {
+        Ok(contents) => serde_json::from_str(&contents).map_err(|error| format!("{}: {}", record_file(id).display(), error))?,
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            if let Some(source) = installation_source(id) {
+                let local = Repository::parse(&source).is_ok_and(|repository| matches!(repository, Repository::Local(_)));
+                if !local {
+                    return Err(format!("{} was downloaded without a signature, download it again", id).into());
+                }
+                if !trust.allow_unsigned {
+                    return Err(format!("{} from {} is not signed, set allow_unsigned_installations to use unsigned local repositories", id, source).into());
+                }
+                return Ok(());
+            }
+            if !trust.allow_unsigned {
+                return Err(format!("{} is not signed, set allow_unsigned_installations to use unsigned local installations", id).into());
+            }
+            return Ok(());
+        },
+        Err(error) => return Err(error.into()),
+    };
+
+    trust.verify(id, record.index.as_bytes(), &record.signature)?;
+    let index = FileIndex::parse(record.index.as_bytes())?;
+    let directory = installation_directory(id);
+    for entry in &index.files {
+        entry.checksum.verify_file(&directory.join(&entry.path))
+            .map_err(|error| format!("{} was modified after it was downloaded: {}", id, error))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Keypair, SecretKey, Signer};
+
+    use super::*;
+
+    const INDEX: &[u8] = br#"{"version": 2, "files": []}"#;
+
+    fn keypair(seed: u8) -> Keypair {
+        let secret = SecretKey::from_bytes(&[seed; 32]).unwrap();
+        let public = PublicKey::from(&secret);
+        Keypair { secret, public }
+    }
+
+    fn sign(keypair: &Keypair, index: &[u8]) -> IndexSignature {
+        IndexSignature {
+            key: hex::encode(keypair.public.to_bytes()),
+            signature: hex::encode(keypair.sign(index).to_bytes()),
+        }
+    }
+
+    fn trusting(keypair: &Keypair) -> TrustStore {
+        TrustStore {
+            keys: vec![hex::encode(keypair.public.to_bytes())],
+            allow_unsigned: false,
+        }
+    }
+
+    #[test]
+    fn accepts_signatures_by_trusted_keys() {
+        let publisher = keypair(1);
+        assert!(trusting(&publisher).verify("base", INDEX, &sign(&publisher, INDEX)).is_ok());
+    }
+
+    #[test]
+    fn rejects_untrusted_keys() {
+        let publisher = keypair(1);
+        let error = trusting(&keypair(2)).verify("base", INDEX, &sign(&publisher, INDEX)).unwrap_err();
+        assert!(error.to_string().contains("not in trusted_keys"), "{}", error);
+        let error = TrustStore::default().verify("base", INDEX, &sign(&publisher, INDEX)).unwrap_err();
+        assert!(error.to_string().contains("no keys are trusted"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_bad_signatures() {
+        let publisher = keypair(1);
+        let trust = trusting(&publisher);
+
+        let other_index = sign(&publisher, br#"{"version": 2, "files": [{"path": "info.json"}]}"#);
+        assert!(trust.verify("base", INDEX, &other_index).is_err());
+
+        let mut garbled = sign(&publisher, INDEX);
+        garbled.signature = "00".repeat(64);
+        assert!(trust.verify("base", INDEX, &garbled).is_err());
+
+        garbled.signature = "not hex".to_string();
+        assert!(trust.verify("base", INDEX, &garbled).is_err());
+    }
+}
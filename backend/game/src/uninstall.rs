@@ -0,0 +1,73 @@
+use std::{error::Error, fs::{self, remove_dir_all}, path::PathBuf};
+
+use crate::{Manifest, installation_directory, manifest::{check_id, manifest_file}, installed_ids, gc::{collect_references, reclaimable_space}, permission::remove_granted_permissions, receipt::{Receipt, ReceiptFile}, repository::remove_installation_source, trust::remove_signature};
+
+#[derive(Debug, Default)]
+pub struct UninstallReport {
+    /// Files the install produced that no other installation needed, including store entries.
+    pub removed: Vec<PathBuf>,
+    /// Bytes freed by removing them, not counting the installation's own files directory.
+    pub freed: u64,
+}
+
+/// Installed installations that name `id` as their parent.
+fn dependents(id: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut dependents = Vec::new();
+    for installed in installed_ids()? {
+        if let Ok(manifest) = Manifest::load(&installed) {
+            if manifest.parent.as_deref() == Some(id) {
+                dependents.push(installed);
+            }
+        }
+    }
+
+    Ok(dependents)
+}
+
+/// Removes an installation, its receipt and every file its install produced that no other
+/// installed installation references. Refuses to remove the parent of another installation, and
+/// anything that is not an installed installation with a manifest.
+pub fn uninstall_installation(id: &str) -> Result<UninstallReport, Box<dyn Error>> {
+    check_id(id)?;
+    if !manifest_file(id).is_file() {
+        return Err(format!("{} is not installed", id).into());
+    }
+
+    let dependents = dependents(id)?;
+    if !dependents.is_empty() {
+        return Err(format!("Cannot uninstall {}, it is the parent of: {}", id, dependents.join(", ")).into());
+    }
+
+    let directory = installation_directory(id);
+    let receipt = Receipt::load(id)?;
+
+    let references = collect_references(Some(id))?;
+    let mut report = UninstallReport::default();
+    let files: Vec<ReceiptFile> = receipt.map(|receipt| receipt.all_files().cloned().collect()).unwrap_or_default();
+    for file in files {
+        let path = PathBuf::from(&file.path);
+        if !path.starts_with(&directory) && !references.paths.contains(&path) && path.is_file() && !report.removed.contains(&path) {
+            report.removed.push(path);
+        }
+
+        if let Some(store) = file.store.map(PathBuf::from) {
+            if !references.entries.contains(&store) && store.is_file() && !report.removed.contains(&store) {
+                report.removed.push(store);
+            }
+        }
+    }
+    report.freed = reclaimable_space(&report.removed);
+
+    for file in &report.removed {
+        fs::remove_file(file)?;
+    }
+    if directory.is_dir() {
+        remove_dir_all(&directory)?;
+    }
+    Receipt::remove(id)?;
+    remove_installation_source(id)?;
+    remove_granted_permissions(id)?;
+    remove_signature(id)?;
+
+    Ok(report)
+}
@@ -0,0 +1,147 @@
+use std::{collections::HashMap, error::Error};
+
+use settings::SettingManager;
+
+use crate::{CancellationToken, Manifest, download_installation, install_installation, network::Network, parse_installation, permission::{PermissionRequest, PermissionsRequired, missing_permissions}, receipt::Receipt, repositories, repository::{Repository, find_installation_file}, runtime::block_on};
+
+/// An installed installation whose repository has something newer for it or for one of its
+/// parents.
+#[derive(Debug, Clone)]
+pub struct InstallationUpdate {
+    pub id: String,
+    pub installed_version: Option<String>,
+    /// Whether the installation itself changed rather than only its parents.
+    pub changed: bool,
+    /// The version the repository serves now, when the installation itself changed.
+    pub available_version: Option<String>,
+    /// Parents in the chain that changed, nearest first.
+    pub changed_parents: Vec<String>,
+    chain: Vec<String>,
+}
+
+/// The manifest an installation's repository serves now, if it differs from the installed one.
+type Change = Option<Manifest>;
+
+/// The change of every installation in the chains of the checked installations.
+type Changes = HashMap<String, Change>;
+
+/// The manifest of the last install of `id` that finished, or `None` when no install of it did.
+/// Receipts from before manifests were recorded fall back to the installation directory.
+fn installed_manifest(id: &str) -> Result<Option<Manifest>, Box<dyn Error>> {
+    match Receipt::load(id)? {
+        Some(Receipt { manifest: Some(manifest), .. }) => Ok(Some(manifest)),
+        Some(_) => Ok(Some(Manifest::load(id)?)),
+        None => Ok(None),
+    }
+}
+
+/// Compares the installed manifest of `id` with the one its repository serves now. Versions are
+/// compared when both manifests have one, the whole manifest otherwise, and an installation
+/// whose install never finished always needs one. Installations no configured repository
+/// serves, such as local development ones, never change.
+fn remote_change(network: &Network, repositories: &[Repository], id: &str) -> Result<Change, Box<dyn Error>> {
+    let installed = installed_manifest(id)?;
+    let (repository, contents) = match block_on(find_installation_file(network, repositories, id, "info.json"))? {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    let file = format!("{}/{}/info.json", repository.location(), id);
+    let contents = String::from_utf8(contents).map_err(|error| format!("{}: {}", file, error))?;
+    let available = Manifest::parse(&contents, &file, id)?;
+
+    let changed = match installed {
+        Some(installed) => match (&installed.version, &available.version) {
+            (Some(installed), Some(available)) => installed != available,
+            _ => installed != available,
+        },
+        None => true,
+    };
+
+    Ok(if changed { Some(available) } else { None })
+}
+
+/// Checks `ids` against the configured repositories. An installation needs an update when it or
+/// any installation in its parent chain changed.
+pub fn check_updates(ids: &[String], settings: &SettingManager) -> Result<Vec<InstallationUpdate>, Box<dyn Error>> {
+    Ok(find_updates(ids, settings)?.0)
+}
+
+/// The updates `ids` need, and the changes they were found from.
+fn find_updates(ids: &[String], settings: &SettingManager) -> Result<(Vec<InstallationUpdate>, Changes), Box<dyn Error>> {
+    let network = Network::new(settings)?;
+    let repositories = repositories(settings)?;
+
+    let mut changes = Changes::new();
+    let mut updates = Vec::new();
+    for id in ids {
+        let installation = parse_installation(id.clone(), settings)?;
+        for member in installation.chain() {
+            if !changes.contains_key(member) {
+                changes.insert(member.clone(), remote_change(&network, &repositories, member)?);
+            }
+        }
+
+        let own = &changes[id];
+        let changed_parents: Vec<String> = installation.chain().iter().skip(1).filter(|parent| changes[*parent].is_some()).cloned().collect();
+        if own.is_none() && changed_parents.is_empty() {
+            continue;
+        }
+
+        updates.push(InstallationUpdate {
+            id: id.clone(),
+            installed_version: installed_manifest(id)?.and_then(|manifest| manifest.version),
+            changed: own.is_some(),
+            available_version: own.as_ref().and_then(|manifest| manifest.version.clone()),
+            changed_parents,
+            chain: installation.chain().to_vec(),
+        });
+    }
+
+    Ok((updates, changes))
+}
+
+/// Fails with `PermissionsRequired` when the manifests `updates` would install declare permissions
+/// that were not granted, so consent is asked for before anything is downloaded.
+fn check_update_permissions(updates: &[InstallationUpdate], changes: &Changes) -> Result<(), Box<dyn Error>> {
+    let mut requests = Vec::new();
+    for update in updates {
+        for member in update.chain.iter().rev() {
+            if requests.iter().any(|request: &PermissionRequest| &request.installation == member) {
+                continue;
+            }
+
+            let manifest = match &changes[member] {
+                Some(available) => available.clone(),
+                None => Manifest::load(member)?,
+            };
+            requests.extend(missing_permissions(member, &manifest)?);
+        }
+    }
+
+    if !requests.is_empty() {
+        return Err(Box::new(PermissionsRequired { requests }));
+    }
+
+    Ok(())
+}
+
+/// Downloads and reinstalls every installation in `ids` that needs an update, returning what was
+/// updated. Installing an installation runs the install scripts of its whole chain, so parents
+/// that are updated along with one of their children are not installed a second time. Fails with
+/// `PermissionsRequired` before downloading anything when the new manifests need consent.
+pub fn update_installations(ids: &[String], settings: &SettingManager, cancellation: &CancellationToken) -> Result<Vec<InstallationUpdate>, Box<dyn Error>> {
+    let (updates, changes) = find_updates(ids, settings)?;
+    check_update_permissions(&updates, &changes)?;
+
+    for update in &updates {
+        let covered = updates.iter().any(|other| other.id != update.id && other.chain.contains(&update.id));
+        if covered {
+            continue;
+        }
+
+        download_installation(update.id.clone(), settings, cancellation)?;
+        install_installation(&parse_installation(update.id.clone(), settings)?, settings, cancellation)?;
+    }
+
+    Ok(updates)
+}
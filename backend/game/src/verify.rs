@@ -0,0 +1,111 @@
+use std::{error::Error, fs::File, io::copy, path::Path};
+
+use settings::SettingManager;
+
+use crate::{Installation, archive::extract_zip_entry, downloader::fetch, hash::{Checksum, sha1_file}, network::Network, receipt::{FileSource, Receipt, ReceiptFile}, runtime::block_on, store::create_unlinked};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileProblem {
+    Missing,
+    Modified,
+}
+
+#[derive(Debug, Clone)]
+pub struct BrokenFile {
+    pub installation: String,
+    pub file: ReceiptFile,
+    pub problem: FileProblem,
+}
+
+#[derive(Debug, Default)]
+pub struct RepairReport {
+    pub repaired: Vec<BrokenFile>,
+    /// Files that could not be recreated, with the reason.
+    pub failed: Vec<(BrokenFile, String)>,
+}
+
+fn check_file(file: &ReceiptFile) -> Result<Option<FileProblem>, Box<dyn Error>> {
+    let path = Path::new(&file.path);
+    let metadata = match path.metadata() {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => return Ok(Some(FileProblem::Missing)),
+    };
+
+    // Receipts written before hashes were recorded only know the path.
+    if file.sha1.is_empty() {
+        return Ok(None);
+    }
+    if metadata.len() != file.size || !sha1_file(path)?.eq_ignore_ascii_case(&file.sha1) {
+        return Ok(Some(FileProblem::Modified));
+    }
+
+    Ok(None)
+}
+
+/// Checks every file recorded in the receipts of `installation` and its parents against the
+/// size and hash captured when it was installed.
+pub fn verify_installation(installation: &Installation) -> Result<Vec<BrokenFile>, Box<dyn Error>> {
+    let mut broken = Vec::new();
+    for id in installation.chain() {
+        let receipt = Receipt::load(id)?.ok_or(format!("{} has not been installed", id))?;
+        for file in receipt.files {
+            if let Some(problem) = check_file(&file)? {
+                broken.push(BrokenFile {
+                    installation: id.clone(),
+                    file,
+                    problem,
+                });
+            }
+        }
+    }
+
+    Ok(broken)
+}
+
+fn repair_file(network: &Network, file: &ReceiptFile) -> Result<(), Box<dyn Error>> {
+    let expected = Checksum {
+        size: Some(file.size),
+        sha256: None,
+        sha1: Some(file.sha1.clone()).filter(|sha1| !sha1.is_empty()),
+    };
+
+    match &file.source {
+        Some(FileSource::Download { url }) => {
+            block_on(fetch(network, url, &file.path, &expected))?;
+        },
+        Some(FileSource::Extract { archive, entry }) => {
+            extract_zip_entry(archive, entry, Path::new(&file.path))?;
+        },
+        Some(FileSource::Copy { from }) => {
+            let mut input = File::open(from)?;
+            let mut output = create_unlinked(&file.path)?;
+            copy(&mut input, &mut output)?;
+        },
+        Some(FileSource::Write) => return Err("written by the install script, reinstall to recreate it".into()),
+        None => return Err("the receipt does not record where it came from, reinstall to recreate it".into()),
+    }
+
+    expected.verify_file(Path::new(&file.path))
+}
+
+/// Recreates the files `verify_installation` reports as broken, downloading only those files
+/// rather than running the install scripts again. Downloads are repaired first, since extracted
+/// and copied files are recreated from them.
+pub fn repair_installation(installation: &Installation, settings: &SettingManager) -> Result<RepairReport, Box<dyn Error>> {
+    let network = Network::new(settings)?;
+    let mut broken = verify_installation(installation)?;
+    broken.sort_by_key(|broken| !matches!(broken.file.source, Some(FileSource::Download { .. })));
+
+    let mut report = RepairReport::default();
+    for file in broken {
+        match repair_file(&network, &file.file) {
+            Ok(()) => report.repaired.push(file),
+            Err(error) => {
+                let error = error.to_string();
+                report.failed.push((file, error));
+            },
+        }
+    }
+
+    Ok(report)
+}
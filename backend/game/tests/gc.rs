@@ -0,0 +1,41 @@
+use std::{env, fs, path::{Path, PathBuf}, process};
+
+use game::collect_garbage;
+
+fn write(path: &str, contents: &str) {
+    let path = Path::new(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+// Garbage collection works on the launcher directory, so this test changes the current
+// directory and must stay the only test in this file.
+#[test]
+fn shared_directories_are_only_swept_once_every_installation_has_a_receipt() {
+    let directory = env::temp_dir().join(format!("game-gc-{}", process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    env::set_current_dir(&directory).unwrap();
+
+    write("installation/files/old/info.json", r#"{"id": "old"}"#);
+    write("libraries/old.jar", "used by old");
+    write("libraries/stale.jar.part", "half a download");
+
+    let report = collect_garbage(true).unwrap();
+    assert_eq!(report.unrecorded, vec!["old".to_string()]);
+    assert!(report.files.is_empty());
+    collect_garbage(false).unwrap();
+    assert!(Path::new("libraries/old.jar").is_file());
+
+    write("installation/receipts/old.json", r#"{"files": [{"path": "libraries/kept.jar"}]}"#);
+    write("libraries/kept.jar", "listed by the receipt");
+    let report = collect_garbage(false).unwrap();
+    assert!(report.unrecorded.is_empty());
+    assert_eq!(report.files, vec![PathBuf::from("libraries/old.jar")]);
+    assert!(Path::new("libraries/kept.jar").is_file());
+    assert!(Path::new("libraries/stale.jar.part").is_file());
+    assert!(!Path::new("libraries/old.jar").exists());
+
+    env::set_current_dir(env::temp_dir()).unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,70 @@
+use std::{env, fs, path::Path, process};
+
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer};
+use game::parse_installation;
+use sha2::{Digest, Sha256};
+
+fn write(path: &str, contents: &str) {
+    let path = Path::new(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+/// Installs `id` as if it was downloaded from `source`, signed by `keypair` when given.
+fn install(id: &str, source: &str, keypair: Option<&Keypair>) {
+    let manifest = format!(r#"{{"id": "{}"}}"#, id);
+    write(&format!("installation/files/{}/info.json", id), &manifest);
+    write(&format!("installation/sources/{}", id), source);
+
+    if let Some(keypair) = keypair {
+        let index = format!(
+            r#"{{"version": 2, "files": [{{"path": "info.json", "size": {}, "sha256": "{}"}}]}}"#,
+            manifest.len(), hex::encode(Sha256::digest(manifest.as_bytes())),
+        );
+        let record = serde_json::json!({
+            "index": index,
+            "signature": {
+                "key": hex::encode(keypair.public.to_bytes()),
+                "signature": hex::encode(keypair.sign(index.as_bytes()).to_bytes()),
+            },
+        });
+        write(&format!("installation/signatures/{}.json", id), &record.to_string());
+    }
+}
+
+// Installations are read from the launcher directory, so this test changes the current
+// directory and must stay the only test in this file.
+#[test]
+fn installed_files_are_checked_against_their_signature() {
+    let directory = env::temp_dir().join(format!("game-trust-{}", process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    env::set_current_dir(&directory).unwrap();
+
+    let secret = SecretKey::from_bytes(&[1; 32]).unwrap();
+    let public = PublicKey::from(&secret);
+    let publisher = Keypair { secret, public };
+    let settings = serde_json::json!({
+        "trusted_keys": [hex::encode(publisher.public.to_bytes())],
+        "allow_unsigned_installations": true,
+    });
+    write("launcher_settings.json", &settings.to_string());
+    let settings = settings::initialize_settings().unwrap();
+
+    install("signed", "https://example.com/installation", Some(&publisher));
+    assert!(parse_installation("signed".to_string(), &settings).is_ok());
+
+    write("installation/files/signed/info.json", r#"{"id": "signed", "name": "changed"}"#);
+    let error = parse_installation("signed".to_string(), &settings).unwrap_err();
+    assert!(error.to_string().contains("modified after it was downloaded"), "{}", error);
+
+    install("local", "file:///srv/installation", None);
+    assert!(parse_installation("local".to_string(), &settings).is_ok());
+
+    install("remote", "https://example.com/installation", None);
+    let error = parse_installation("remote".to_string(), &settings).unwrap_err();
+    assert!(error.to_string().contains("without a signature"), "{}", error);
+
+    env::set_current_dir(env::temp_dir()).unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -2,6 +2,7 @@ use std::{collections::HashMap, error::Error, fs::File, io::{Read, Write}};
 
 use serde_json::{Value, Map, Number};
 
+#[derive(Clone)]
 pub enum Setting {
     Boolean(bool),
     Integer(i32),
@@ -10,6 +11,7 @@ pub enum Setting {
     Null,
 }
 
+#[derive(Clone)]
 pub struct SettingManager {
     settings: HashMap<String, Setting>
 }
@@ -58,6 +60,18 @@ pub fn initialize_settings() -> Result<SettingManager, Box<dyn Error>> {
         map.insert("memory".to_string(), Setting::Integer(1024));
         //map.insert("java_executable".to_string(), Setting::String("java".into()));
         map.insert("developer_mode".to_string(), Setting::Boolean(false));
+        map.insert("download_threads".to_string(), Setting::Integer(8));
+        map.insert("download_retries".to_string(), Setting::Integer(3));
+        map.insert("download_connect_timeout".to_string(), Setting::Integer(10));
+        map.insert("download_read_timeout".to_string(), Setting::Integer(30));
+        // Downloads from http repositories must be signed by one of these hex encoded ed25519
+        // public keys. None are trusted by default, so the key of whoever publishes the
+        // repositories below has to be added before anything can be installed from them.
+        map.insert("trusted_keys".to_string(), Setting::StringArray(Vec::new()));
+        map.insert("allow_unsigned_installations".to_string(), Setting::Boolean(false));
+        map.insert("repositories".to_string(), Setting::StringArray(vec![
+            "https://raw.githubusercontent.com/proton-launcher/asset/main/installation".to_string(),
+        ]));
 
         map
     };
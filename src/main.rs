@@ -2,19 +2,116 @@ use std::{
     env::set_current_dir,
     error::Error,
     fs::File,
-    io::{stdin, BufReader, Write},
+    io::{stdin, stdout, BufReader, Write},
     path::Path,
+    process::exit,
+    sync::{Arc, Mutex},
 };
 
 use authentication::{authenticate, Profile};
 use game::{
-    download_installation, install_installation, parse_installation, run_installation, RunArguments,
+    collect_garbage, download_installation, grant_permissions, install_installation, installed_ids,
+    is_cancelled, list_installations, parse_installation, permissions_required,
+    repair_installation, run_installation, search_installations, uninstall_installation,
+    update_installations, validate_installation, verify_installation, BrokenFile,
+    CancellationToken, FileProblem, PermissionRequest, RunArguments,
 };
 use settings::{initialize_settings, Setting, SettingManager};
 
+mod progress;
+
+fn format_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", size, units[unit])
+}
+
+/// Formats seconds since the unix epoch as a UTC date.
+fn format_date(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn describe_broken_file(broken: &BrokenFile) -> String {
+    let problem = match broken.problem {
+        FileProblem::Missing => "missing",
+        FileProblem::Modified => "modified",
+    };
+
+    format!(
+        "{}: {} ({})",
+        problem, broken.file.path, broken.installation
+    )
+}
+
+/// Asks the user to grant the permissions `requests` declare, recording them when accepted.
+fn consent(requests: &[PermissionRequest]) -> Result<bool, Box<dyn Error>> {
+    for request in requests {
+        println!("{} requests permission to:", request.installation);
+        for line in request.permissions.describe() {
+            println!("    {}", line);
+        }
+    }
+    print!("Allow? [y/N] ");
+    stdout().flush()?;
+
+    let mut answer = String::new();
+    stdin().read_line(&mut answer)?;
+    if !matches!(answer.trim(), "y" | "Y" | "yes") {
+        return Ok(false);
+    }
+
+    for request in requests {
+        grant_permissions(&request.installation, &request.permissions)?;
+    }
+
+    Ok(true)
+}
+
 struct State {
     current_profile: Option<Profile>,
     setting_manager: SettingManager,
+    /// The token of the running download or install, which Ctrl-C cancels.
+    operation: Arc<Mutex<Option<CancellationToken>>>,
+}
+
+impl State {
+    /// Runs `operation` so that Ctrl-C cancels it, returning `None` when it was cancelled.
+    fn cancellable<T>(
+        &self,
+        operation: impl FnOnce(&CancellationToken) -> Result<T, Box<dyn Error>>,
+    ) -> Result<Option<T>, Box<dyn Error>> {
+        let cancellation = CancellationToken::new();
+        *self.operation.lock().unwrap() = Some(cancellation.clone());
+        let result = operation(&cancellation);
+        *self.operation.lock().unwrap() = None;
+
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(error) if is_cancelled(error.as_ref()) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -22,12 +119,20 @@ fn main() -> Result<(), Box<dyn Error>> {
         let _ = set_current_dir("runtime");
     }
     println!("Proton Launcher CLI:");
+    let _progress = progress::render_progress();
 
     let mut state = State {
         current_profile: None,
         setting_manager: initialize_settings()?,
+        operation: Arc::new(Mutex::new(None)),
     };
 
+    let operation = state.operation.clone();
+    ctrlc::set_handler(move || match operation.lock().unwrap().as_ref() {
+        Some(cancellation) => cancellation.cancel(),
+        None => exit(130),
+    })?;
+
     let account_file = Path::new("account.json");
     if account_file.exists() {
         let file = File::open(account_file)?;
@@ -65,22 +170,51 @@ fn main() -> Result<(), Box<dyn Error>> {
             },
             "game" => match arguments[1] {
                 "install" => {
-                    println!("Installing...");
-                    if let Setting::Boolean(value) = state
-                        .setting_manager
-                        .get_setting("developer_mode".into())
-                        .unwrap()
-                    {
-                        if !value {
-                            download_installation(arguments[2].to_string())?;
+                    // Downloads stop before replacing the installed files while permissions need
+                    // consent, which is asked for before trying again.
+                    let installed = loop {
+                        let result = state.cancellable(|cancellation| {
+                            if let Setting::Boolean(value) = state
+                                .setting_manager
+                                .get_setting("developer_mode".into())
+                                .unwrap()
+                            {
+                                if !value {
+                                    download_installation(
+                                        arguments[2].to_string(),
+                                        &state.setting_manager,
+                                        cancellation,
+                                    )?;
+                                }
+                            }
+                            let installation = parse_installation(
+                                arguments[2].to_string(),
+                                &state.setting_manager,
+                            )?;
+                            install_installation(
+                                &installation,
+                                &state.setting_manager,
+                                cancellation,
+                            )
+                        });
+                        match result {
+                            Err(error) => match permissions_required(error.as_ref()) {
+                                Some(required) if consent(&required.requests)? => continue,
+                                Some(_) => break Some(false),
+                                None => return Err(error),
+                            },
+                            Ok(installed) => break installed.map(|()| true),
                         }
+                    };
+                    match installed {
+                        Some(true) => println!("Finished!"),
+                        Some(false) => println!("Permissions not granted, nothing was installed"),
+                        None => (),
                     }
-                    let installation = parse_installation(arguments[2].to_string())?;
-                    install_installation(&installation)?;
-                    println!("Finished!");
                 }
                 "launch" => {
-                    let installation = parse_installation(arguments[2].to_string())?;
+                    let installation =
+                        parse_installation(arguments[2].to_string(), &state.setting_manager)?;
                     let profile = state
                         .current_profile
                         .as_ref()
@@ -95,6 +229,173 @@ fn main() -> Result<(), Box<dyn Error>> {
                         &state.setting_manager,
                     )?;
                 }
+                "validate" => {
+                    let problems = validate_installation(arguments[2])?;
+                    if problems.is_empty() {
+                        println!("No problems found in {}", arguments[2]);
+                    } else {
+                        for problem in &problems {
+                            println!("{}", problem);
+                        }
+                        println!("{} problem(s) found in {}", problems.len(), arguments[2]);
+                    }
+                }
+                "verify" => {
+                    let installation =
+                        parse_installation(arguments[2].to_string(), &state.setting_manager)?;
+                    let broken = verify_installation(&installation)?;
+                    for file in &broken {
+                        println!("{}", describe_broken_file(file));
+                    }
+                    if broken.is_empty() {
+                        println!("{} is intact", installation.chain_display());
+                    } else {
+                        println!("{} broken file(s)", broken.len());
+                    }
+                }
+                "repair" => {
+                    let installation =
+                        parse_installation(arguments[2].to_string(), &state.setting_manager)?;
+                    let report = repair_installation(&installation, &state.setting_manager)?;
+                    for file in &report.repaired {
+                        println!("repaired {}", describe_broken_file(file));
+                    }
+                    for (file, error) in &report.failed {
+                        println!("could not repair {}: {}", describe_broken_file(file), error);
+                    }
+                    println!(
+                        "Repaired {} file(s), {} could not be repaired",
+                        report.repaired.len(),
+                        report.failed.len()
+                    );
+                }
+                "list" => {
+                    let installations = list_installations()?;
+                    for installation in &installations {
+                        println!(
+                            "{} {} ({})",
+                            installation.id,
+                            installation.version.as_deref().unwrap_or("unversioned"),
+                            installation.name
+                        );
+                        if let Some(parent) = &installation.parent {
+                            println!("    parent: {}", parent);
+                        }
+                        match installation.installed_at {
+                            Some(installed_at) => {
+                                println!("    installed: {}", format_date(installed_at))
+                            }
+                            None => println!("    installed: not yet"),
+                        }
+                        println!("    disk usage: {}", format_size(installation.disk_usage));
+                        println!(
+                            "    source: {}",
+                            installation.source.as_deref().unwrap_or("local")
+                        );
+                    }
+                    println!("{} installation(s)", installations.len());
+                }
+                "search" => {
+                    let term = arguments.get(2).copied().unwrap_or("");
+                    let results = search_installations(term, &state.setting_manager)?;
+                    for result in &results {
+                        println!(
+                            "{} {} ({}){}",
+                            result.entry.id,
+                            result.entry.version.as_deref().unwrap_or("unversioned"),
+                            result.entry.display_name(),
+                            if result.installed { " [installed]" } else { "" }
+                        );
+                        if let Some(description) = &result.entry.description {
+                            println!("    {}", description);
+                        }
+                        println!("    from {}", result.repository);
+                    }
+                    println!("{} result(s)", results.len());
+                }
+                "update" => {
+                    let ids = match arguments.get(2) {
+                        Some(&"--all") => installed_ids()?,
+                        Some(id) => vec![id.to_string()],
+                        None => {
+                            println!("Usage: game update <id|--all>");
+                            continue;
+                        }
+                    };
+                    // Updates may declare new permissions, which are asked for before trying again.
+                    let updates = loop {
+                        let result = state.cancellable(|cancellation| {
+                            update_installations(&ids, &state.setting_manager, cancellation)
+                        });
+                        match result {
+                            Err(error) => match permissions_required(error.as_ref()) {
+                                Some(required) if consent(&required.requests)? => continue,
+                                Some(_) => break None,
+                                None => return Err(error),
+                            },
+                            Ok(updates) => break updates,
+                        }
+                    };
+                    let updates = match updates {
+                        Some(updates) => updates,
+                        None => continue,
+                    };
+                    for update in &updates {
+                        let mut reasons = Vec::new();
+                        if update.changed {
+                            reasons.push(format!(
+                                "{} -> {}",
+                                update.installed_version.as_deref().unwrap_or("unversioned"),
+                                update.available_version.as_deref().unwrap_or("unversioned")
+                            ));
+                        }
+                        if !update.changed_parents.is_empty() {
+                            reasons.push(format!(
+                                "parent changed: {}",
+                                update.changed_parents.join(", ")
+                            ));
+                        }
+                        println!("Updated {} ({})", update.id, reasons.join(", "));
+                    }
+                    if updates.is_empty() {
+                        println!("Everything is up to date");
+                    }
+                }
+                "uninstall" => {
+                    let report = uninstall_installation(arguments[2])?;
+                    println!(
+                        "Uninstalled {}, removed {} file(s) and freed {}",
+                        arguments[2],
+                        report.removed.len(),
+                        format_size(report.freed)
+                    );
+                }
+                "gc" => {
+                    let dry_run = arguments.get(2) == Some(&"--dry-run");
+                    let report = collect_garbage(dry_run)?;
+                    if !report.unrecorded.is_empty() {
+                        println!(
+                            "Warning: skipped libraries, assets and natives since {} have no install receipt, install them again to include them",
+                            report.unrecorded.join(", ")
+                        );
+                    }
+                    if dry_run {
+                        for file in &report.files {
+                            println!("{}", file.display());
+                        }
+                        println!(
+                            "Would remove {} file(s), reclaiming {}",
+                            report.files.len(),
+                            format_size(report.reclaimable)
+                        );
+                    } else {
+                        println!(
+                            "Removed {} file(s), reclaimed {}",
+                            report.files.len(),
+                            format_size(report.reclaimable)
+                        );
+                    }
+                }
                 _ => (),
             },
             "settings" => {
@@ -0,0 +1,211 @@
+use std::{
+    collections::HashMap,
+    io::{stdout, Write},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use game::{subscribe, Phase, ProgressEvent, Subscription};
+
+use crate::format_size;
+
+const BAR_WIDTH: usize = 30;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+struct Download {
+    size: Option<u64>,
+    downloaded: u64,
+    finished: bool,
+}
+
+/// Draws the downloads of the running operation as a single progress bar on the last line,
+/// printing everything else above it.
+#[derive(Default)]
+struct Renderer {
+    started: Option<Instant>,
+    /// Keyed by destination path, since several paths may be fetched from the same url.
+    downloads: HashMap<String, Download>,
+    received: u64,
+    last_drawn: Option<Instant>,
+    bar_visible: bool,
+}
+
+impl Renderer {
+    fn handle(&mut self, event: &ProgressEvent) {
+        match event {
+            ProgressEvent::PhaseStarted {
+                installation,
+                phase,
+            } => {
+                let action = match phase {
+                    Phase::Download => "Downloading",
+                    Phase::Install => "Installing",
+                    Phase::Launch => "Preparing launch of",
+                };
+                self.print(&format!("{} {}...", action, installation));
+            }
+            ProgressEvent::PhaseFinished {
+                installation,
+                phase,
+                elapsed,
+            } => {
+                let action = match phase {
+                    Phase::Download => "Downloaded",
+                    Phase::Install => "Installed",
+                    Phase::Launch => "Prepared launch of",
+                };
+                if *phase != Phase::Download && !self.downloads.is_empty() {
+                    let summary = format!(
+                        "Fetched {} file(s), {} downloaded",
+                        self.downloads.len(),
+                        format_size(self.received)
+                    );
+                    self.reset();
+                    self.print(&summary);
+                }
+                self.print(&format!(
+                    "{} {} in {:.1}s",
+                    action,
+                    installation,
+                    elapsed.as_secs_f64()
+                ));
+            }
+            ProgressEvent::FileQueued { path, size, .. } => {
+                self.started.get_or_insert_with(Instant::now);
+                self.downloads.insert(
+                    path.clone(),
+                    Download {
+                        size: *size,
+                        downloaded: 0,
+                        finished: false,
+                    },
+                );
+                self.draw(false);
+            }
+            ProgressEvent::BytesDownloaded {
+                path,
+                bytes,
+                downloaded,
+                total,
+                ..
+            } => {
+                self.received += bytes;
+                if let Some(download) = self.downloads.get_mut(path) {
+                    download.downloaded = *downloaded;
+                    download.size = download.size.or(*total);
+                }
+                self.draw(false);
+            }
+            ProgressEvent::FileFinished { path, size, .. } => {
+                if let Some(download) = self.downloads.get_mut(path) {
+                    download.size = Some(*size);
+                    download.downloaded = *size;
+                    download.finished = true;
+                }
+                self.draw(true);
+            }
+            ProgressEvent::Extracting {
+                archive,
+                index,
+                total,
+                ..
+            } => {
+                if index + 1 == *total {
+                    self.print(&format!("Extracted {} entries of {}", total, archive));
+                }
+            }
+            ProgressEvent::ScriptLog { message, .. } => self.print(message),
+            ProgressEvent::Error { message } => self.print(&format!("Error: {}", message)),
+            ProgressEvent::Cancelled { installation } => {
+                self.reset();
+                self.print(&format!("Cancelled {}", installation));
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.started = None;
+        self.downloads.clear();
+        self.received = 0;
+    }
+
+    fn clear(&mut self) {
+        if self.bar_visible {
+            print!("\r\x1b[2K");
+            self.bar_visible = false;
+        }
+    }
+
+    /// Prints a line above the progress bar.
+    fn print(&mut self, line: &str) {
+        self.clear();
+        println!("{}", line);
+        self.draw(true);
+    }
+
+    fn draw(&mut self, force: bool) {
+        let started = match self.started {
+            Some(started) if !self.downloads.is_empty() => started,
+            _ => return,
+        };
+        let now = Instant::now();
+        if !force
+            && self
+                .last_drawn
+                .is_some_and(|drawn| now - drawn < REDRAW_INTERVAL)
+        {
+            return;
+        }
+        self.last_drawn = Some(now);
+
+        let queued = self.downloads.len();
+        let finished = self
+            .downloads
+            .values()
+            .filter(|download| download.finished)
+            .count();
+        let sizes: Option<u64> = self.downloads.values().map(|download| download.size).sum();
+        let fraction = match sizes {
+            Some(total) if total > 0 => {
+                let done: u64 = self
+                    .downloads
+                    .values()
+                    .map(|download| download.downloaded)
+                    .sum();
+                done as f64 / total as f64
+            }
+            _ => finished as f64 / queued as f64,
+        }
+        .min(1.0);
+
+        let elapsed = now - started;
+        let throughput = self.received as f64 / elapsed.as_secs_f64().max(0.001);
+        let eta = if fraction > 0.0 && fraction < 1.0 {
+            let remaining = elapsed.as_secs_f64() * (1.0 - fraction) / fraction;
+            format!(", ETA {}s", remaining.ceil() as u64)
+        } else {
+            String::new()
+        };
+
+        let filled = (fraction * BAR_WIDTH as f64) as usize;
+        print!(
+            "\r\x1b[2K[{}{}] {:3.0}% {}/{} files, {} at {}/s{}",
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            fraction * 100.0,
+            finished,
+            queued,
+            format_size(self.received),
+            format_size(throughput as u64),
+            eta
+        );
+        let _ = stdout().flush();
+        self.bar_visible = true;
+    }
+}
+
+/// Renders progress events on the terminal for as long as the subscription is kept.
+pub fn render_progress() -> Subscription {
+    let renderer = Mutex::new(Renderer::default());
+    subscribe(move |event| renderer.lock().unwrap().handle(event))
+}